@@ -6,7 +6,15 @@ if ! which -q zbus-xmlgen &>/dev/null; then
     exit 1
 fi
 
+# the output is used verbatim. lints it trips are allowed on the module
+# declarations in src/dbus/mod.rs
 set -x
+zbus-xmlgen dbus/org.hails.dprom.Collect1.xml > src/dbus/collect.rs
 zbus-xmlgen dbus/org.hails.dprom.Counter1.xml > src/dbus/counter.rs
 zbus-xmlgen dbus/org.hails.dprom.DProm1.xml > src/dbus/dprom.rs
+zbus-xmlgen dbus/org.hails.dprom.DProm2.xml > src/dbus/dprom2.rs
 zbus-xmlgen dbus/org.hails.dprom.Gauge1.xml > src/dbus/gauge.rs
+zbus-xmlgen dbus/org.hails.dprom.Histogram1.xml > src/dbus/histogram.rs
+zbus-xmlgen dbus/org.hails.dprom.Info1.xml > src/dbus/info.rs
+zbus-xmlgen dbus/org.hails.dprom.NativeHistogram1.xml > src/dbus/native_histogram.rs
+zbus-xmlgen dbus/org.hails.dprom.StateSet1.xml > src/dbus/state_set.rs
@@ -14,10 +14,26 @@ use zbus::dbus_proxy;
 
 #[dbus_proxy(interface = "org.hails.dprom.Counter1")]
 trait Counter1 {
+    /// Error property
+    #[dbus_proxy(property)]
+    fn error(&self) -> zbus::Result<String>;
+
+    /// Exemplar property
+    #[dbus_proxy(property)]
+    fn exemplar(&self) -> zbus::Result<(std::collections::HashMap<String, String>, f64, i64)>;
+
+    /// LastSuccess property
+    #[dbus_proxy(property)]
+    fn last_success(&self) -> zbus::Result<i64>;
+
     /// Name property
     #[dbus_proxy(property)]
     fn name(&self) -> zbus::Result<String>;
 
+    /// Timestamp property
+    #[dbus_proxy(property)]
+    fn timestamp(&self) -> zbus::Result<i64>;
+
     /// Value property
     #[dbus_proxy(property)]
     fn value(&self) -> zbus::Result<u64>;
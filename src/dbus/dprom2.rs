@@ -0,0 +1,27 @@
+//! # DBus interface proxy for: `org.hails.dprom.DProm2`
+//!
+//! This code was generated by `zbus-xmlgen` `3.0.0` from DBus introspection data.
+//! Source: `org.hails.dprom.DProm2.xml`.
+//!
+//! You may prefer to adapt it, instead of using it verbatim.
+//!
+//! More information can be found in the
+//! [Writing a client proxy](https://dbus.pages.freedesktop.org/zbus/client.html)
+//! section of the zbus documentation.
+//!
+
+use zbus::dbus_proxy;
+
+#[dbus_proxy(interface = "org.hails.dprom.DProm2")]
+trait DProm2 {
+    /// GetValues method
+    fn get_values(&self) -> zbus::Result<Vec<(zbus::zvariant::OwnedObjectPath, f64, i64, String)>>;
+
+    /// ValuesChanged signal
+    #[dbus_proxy(signal)]
+    fn values_changed(&self, values: Vec<(zbus::zvariant::ObjectPath<'_>, f64, i64, &str)>) -> zbus::Result<()>;
+
+    /// Metrics property
+    #[dbus_proxy(property)]
+    fn metrics(&self) -> zbus::Result<Vec<zbus::zvariant::OwnedObjectPath>>;
+}
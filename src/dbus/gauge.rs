@@ -14,10 +14,22 @@ use zbus::dbus_proxy;
 
 #[dbus_proxy(interface = "org.hails.dprom.Gauge1")]
 trait Gauge1 {
+    /// Error property
+    #[dbus_proxy(property)]
+    fn error(&self) -> zbus::Result<String>;
+
+    /// LastSuccess property
+    #[dbus_proxy(property)]
+    fn last_success(&self) -> zbus::Result<i64>;
+
     /// Name property
     #[dbus_proxy(property)]
     fn name(&self) -> zbus::Result<String>;
 
+    /// Timestamp property
+    #[dbus_proxy(property)]
+    fn timestamp(&self) -> zbus::Result<i64>;
+
     /// Value property
     #[dbus_proxy(property)]
     fn value(&self) -> zbus::Result<f64>;
@@ -0,0 +1,36 @@
+//! # DBus interface proxy for: `org.hails.dprom.Histogram1`
+//!
+//! This code was generated by `zbus-xmlgen` `3.0.0` from DBus introspection data.
+//! Source: `org.hails.dprom.Histogram1.xml`.
+//!
+//! You may prefer to adapt it, instead of using it verbatim.
+//!
+//! More information can be found in the
+//! [Writing a client proxy](https://dbus.pages.freedesktop.org/zbus/client.html)
+//! section of the zbus documentation.
+//!
+
+use zbus::dbus_proxy;
+
+#[dbus_proxy(interface = "org.hails.dprom.Histogram1")]
+trait Histogram1 {
+    /// Buckets property
+    #[dbus_proxy(property)]
+    fn buckets(&self) -> zbus::Result<Vec<(f64, u64)>>;
+
+    /// Count property
+    #[dbus_proxy(property)]
+    fn count(&self) -> zbus::Result<u64>;
+
+    /// Exemplars property
+    #[dbus_proxy(property)]
+    fn exemplars(&self) -> zbus::Result<Vec<(std::collections::HashMap<String, String>, f64, i64)>>;
+
+    /// Name property
+    #[dbus_proxy(property)]
+    fn name(&self) -> zbus::Result<String>;
+
+    /// Sum property
+    #[dbus_proxy(property)]
+    fn sum(&self) -> zbus::Result<f64>;
+}
@@ -0,0 +1,24 @@
+//! # DBus interface proxy for: `org.hails.dprom.Info1`
+//!
+//! This code was generated by `zbus-xmlgen` `3.0.0` from DBus introspection data.
+//! Source: `org.hails.dprom.Info1.xml`.
+//!
+//! You may prefer to adapt it, instead of using it verbatim.
+//!
+//! More information can be found in the
+//! [Writing a client proxy](https://dbus.pages.freedesktop.org/zbus/client.html)
+//! section of the zbus documentation.
+//!
+
+use zbus::dbus_proxy;
+
+#[dbus_proxy(interface = "org.hails.dprom.Info1")]
+trait Info1 {
+    /// Labels property
+    #[dbus_proxy(property)]
+    fn labels(&self) -> zbus::Result<std::collections::HashMap<String, String>>;
+
+    /// Name property
+    #[dbus_proxy(property)]
+    fn name(&self) -> zbus::Result<String>;
+}
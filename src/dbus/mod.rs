@@ -1,3 +1,11 @@
+pub mod collect;
 pub mod counter;
 pub mod dprom;
+pub mod dprom2;
 pub mod gauge;
+// these modules are generated by generate.sh, lints go here
+#[allow(clippy::type_complexity)]
+pub mod histogram;
+pub mod info;
+pub mod native_histogram;
+pub mod state_set;
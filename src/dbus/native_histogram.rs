@@ -0,0 +1,56 @@
+//! # DBus interface proxy for: `org.hails.dprom.NativeHistogram1`
+//!
+//! This code was generated by `zbus-xmlgen` `3.0.0` from DBus introspection data.
+//! Source: `org.hails.dprom.NativeHistogram1.xml`.
+//!
+//! You may prefer to adapt it, instead of using it verbatim.
+//!
+//! More information can be found in the
+//! [Writing a client proxy](https://dbus.pages.freedesktop.org/zbus/client.html)
+//! section of the zbus documentation.
+//!
+
+use zbus::dbus_proxy;
+
+#[dbus_proxy(interface = "org.hails.dprom.NativeHistogram1")]
+trait NativeHistogram1 {
+    /// Count property
+    #[dbus_proxy(property)]
+    fn count(&self) -> zbus::Result<u64>;
+
+    /// Name property
+    #[dbus_proxy(property)]
+    fn name(&self) -> zbus::Result<String>;
+
+    /// NegativeDeltas property
+    #[dbus_proxy(property)]
+    fn negative_deltas(&self) -> zbus::Result<Vec<i64>>;
+
+    /// NegativeSpans property
+    #[dbus_proxy(property)]
+    fn negative_spans(&self) -> zbus::Result<Vec<(i32, u32)>>;
+
+    /// PositiveDeltas property
+    #[dbus_proxy(property)]
+    fn positive_deltas(&self) -> zbus::Result<Vec<i64>>;
+
+    /// PositiveSpans property
+    #[dbus_proxy(property)]
+    fn positive_spans(&self) -> zbus::Result<Vec<(i32, u32)>>;
+
+    /// Schema property
+    #[dbus_proxy(property)]
+    fn schema(&self) -> zbus::Result<i32>;
+
+    /// Sum property
+    #[dbus_proxy(property)]
+    fn sum(&self) -> zbus::Result<f64>;
+
+    /// ZeroCount property
+    #[dbus_proxy(property)]
+    fn zero_count(&self) -> zbus::Result<u64>;
+
+    /// ZeroThreshold property
+    #[dbus_proxy(property)]
+    fn zero_threshold(&self) -> zbus::Result<f64>;
+}
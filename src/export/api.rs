@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::future;
+use futures::stream::{self, StreamExt};
+use serde_derive::Serialize;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use warp::reply::Response;
+use warp::sse::Event;
+use warp::{Filter, Rejection, Reply};
+
+use crate::export::format;
+use crate::export::http::{bad_request, query};
+use crate::export::live::{Entry, LiveMetrics};
+use crate::export::metric::{MetricName, MetricValue, Source};
+use crate::export::selector::SeriesFilter;
+use crate::export::status::{BusStatus, Status};
+use crate::shutdown::Shutdown;
+
+/// JSON API routes under /api/v1, for dashboards and debugging. These serve
+/// the live metrics only, so metrics collected through Collect1 at scrape
+/// time don't appear in them. Event streams end on shutdown, rather than
+/// holding it up until the shutdown timeout
+pub fn routes(live: Arc<LiveMetrics>, status: Arc<Status>, shutdown: Shutdown)
+    -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone
+{
+    let metrics = warp::path!("api" / "v1" / "metrics")
+        .map({
+            let live = live.clone();
+            move || metrics(&live)
+        });
+
+    let buses = warp::path!("api" / "v1" / "buses")
+        .map(move || buses(&status));
+
+    let stream = warp::path!("api" / "v1" / "stream")
+        .and(query())
+        .map(move |query: String| stream(&live, &query, shutdown.clone()));
+
+    metrics.or(buses).unify().or(stream).unify()
+}
+
+#[derive(Serialize)]
+struct MetricsResponse<'a> {
+    metrics: Vec<MetricJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct MetricJson<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    value: &'a MetricValue,
+    #[serde(flatten)]
+    source: &'a Source,
+    /// publisher supplied sample time, unix milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+    /// unix timestamp in seconds
+    updated: f64,
+}
+
+impl<'a> MetricJson<'a> {
+    fn new(name: &'a MetricName, entry: &'a Entry) -> Self {
+        MetricJson {
+            name: name.as_str(),
+            value: &entry.value,
+            source: &entry.source,
+            timestamp: entry.timestamp,
+            updated: unix_secs(entry.updated),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RemovedJson<'a> {
+    name: &'a str,
+}
+
+fn metrics(live: &LiveMetrics) -> Response {
+    let map = live.read();
+
+    let metrics = map.iter()
+        .map(|(name, entry)| MetricJson::new(name, entry))
+        .collect();
+
+    warp::reply::json(&MetricsResponse { metrics }).into_response()
+}
+
+#[derive(Serialize)]
+struct BusesResponse {
+    buses: Vec<BusStatus>,
+}
+
+fn buses(status: &Status) -> Response {
+    warp::reply::json(&BusesResponse { buses: status.buses() }).into_response()
+}
+
+/// Server-sent event stream of metric changes, optionally filtered by the
+/// same `name[]` and `match[]` parameters as /metrics. Starts with an
+/// `update` event for every current metric, followed by `update` and
+/// `remove` events as they happen. Clients which can't keep up receive a
+/// `lagged` event with the number of updates they missed.
+fn stream(live: &LiveMetrics, query: &str, shutdown: Shutdown) -> Response {
+    let filter = match SeriesFilter::from_query(query) {
+        Ok(filter) => filter,
+        Err(e) => return bad_request(e),
+    };
+
+    // subscribe before taking the snapshot so nothing is missed in between
+    let updates = BroadcastStream::new(live.subscribe());
+
+    let snapshot = live.read().iter()
+        .map(|(name, entry)| Ok((name.clone(), Some(entry.clone()))))
+        .collect::<Vec<_>>();
+
+    // removals don't say what type of metric went away, and so how it was
+    // exposed, so they're passed on for metrics whose updates were. a
+    // metric whose labels change so it's no longer selected is removed too:
+    let mut sent = HashSet::new();
+
+    let events = stream::iter(snapshot)
+        .chain(updates)
+        .filter_map(move |update| future::ready(match update {
+            Ok((name, Some(entry))) if format::selects(&filter, name.as_str(), &entry.value) => {
+                sent.insert(name.clone());
+
+                Event::default()
+                    .event("update")
+                    .json_data(MetricJson::new(&name, &entry))
+                    .ok()
+            }
+            Ok((name, _)) if sent.remove(&name) => {
+                Event::default()
+                    .event("remove")
+                    .json_data(RemovedJson { name: name.as_str() })
+                    .ok()
+            }
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                Some(Event::default().event("lagged").data(missed.to_string()))
+            }
+        }))
+        .map(Ok::<_, Infallible>)
+        .take_until(shutdown.wait());
+
+    warp::sse::reply(warp::sse::keep_alive().stream(events)).into_response()
+}
+
+fn unix_secs(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default()
+}
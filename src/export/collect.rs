@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use futures::future;
+use zbus::names::UniqueName;
+
+use crate::dbus::collect::Collect1Proxy;
+use crate::export::live::{Entry, MetricMap};
+use crate::export::metric::{MetricName, MetricValue, Source};
+
+const DPROM_PATH: &str = "/org/hails/dprom";
+
+/// Buses to be sampled at scrape time through Collect1, rather than through
+/// signals as values change. Only used in pull mode.
+pub struct Collector {
+    log: slog::Logger,
+    timeout: Duration,
+    buses: Mutex<BTreeMap<BusKey, Arc<zbus::Connection>>>,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct BusKey {
+    connection: &'static str,
+    bus: UniqueName<'static>,
+}
+
+/// Registration of a bus in `Collector`, removed again on drop.
+pub struct CollectHandle<'a> {
+    collector: &'a Collector,
+    key: BusKey,
+}
+
+impl Collector {
+    pub fn new(log: slog::Logger, timeout: Duration) -> Self {
+        Collector {
+            log,
+            timeout,
+            buses: Default::default(),
+        }
+    }
+
+    pub fn register(
+        &self,
+        connection: &'static str,
+        conn: Arc<zbus::Connection>,
+        bus: UniqueName<'static>,
+    ) -> CollectHandle<'_> {
+        let key = BusKey { connection, bus };
+        self.buses.lock().unwrap().insert(key.clone(), conn);
+
+        CollectHandle {
+            collector: self,
+            key,
+        }
+    }
+
+    /// Whether the bus serves the Collect1 interface, determined by
+    /// introspection so that Collect itself is only ever called at scrape
+    /// time. Buses which don't answer within the collect timeout are
+    /// assumed not to.
+    pub async fn supports(&self, conn: &zbus::Connection, bus: UniqueName<'static>) -> zbus::Result<bool> {
+        let introspect = async {
+            let introspectable = zbus::fdo::IntrospectableProxy::builder(conn)
+                .destination(bus)?
+                .path(DPROM_PATH)?
+                .cache_properties(zbus::CacheProperties::No)
+                .build()
+                .await?;
+
+            introspectable.introspect().await.map_err(zbus::Error::from)
+        };
+
+        match tokio::time::timeout(self.timeout, introspect).await {
+            Ok(xml) => {
+                let node = zbus::xml::Node::from_reader(xml?.as_bytes())?;
+                Ok(node.interfaces().iter().any(|iface| iface.name() == "org.hails.dprom.Collect1"))
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Calls Collect on every registered bus concurrently. Buses which fail
+    /// or don't respond within the timeout are logged and left out.
+    pub async fn collect(&self) -> MetricMap {
+        let buses = self.buses.lock().unwrap().clone();
+
+        let results = future::join_all(buses.into_iter().map(|(key, conn)| async move {
+            let result = tokio::time::timeout(self.timeout, collect_bus(&conn, &key)).await
+                .context("timed out")
+                .and_then(|result| result);
+
+            (key, result)
+        })).await;
+
+        let now = SystemTime::now();
+        let mut map = MetricMap::new();
+
+        for (key, result) in results {
+            let values = match result {
+                Ok(values) => values,
+                Err(e) => {
+                    slog::warn!(self.log, "error collecting from bus: {:?}", e;
+                        "dbus" => key.connection, "bus" => key.bus.to_string());
+                    continue;
+                }
+            };
+
+            let source = Arc::new(Source {
+                connection: key.connection,
+                bus: key.bus.to_string(),
+                path: DPROM_PATH.to_owned(),
+            });
+
+            for (name, value, timestamp, error) in values {
+                let name = MetricName::from(name);
+                let timestamp = (timestamp != 0).then_some(timestamp);
+
+                if !error.is_empty() {
+                    slog::debug!(self.log, "{} failed: {}", name, error;
+                        "dbus" => key.connection, "bus" => key.bus.to_string());
+                }
+
+                let entry = |value| Entry {
+                    value: MetricValue::Gauge(value),
+                    timestamp,
+                    // only exposed for counters and histograms, which
+                    // aren't collected:
+                    created: now,
+                    source: source.clone(),
+                    updated: now,
+                };
+
+                map.insert(name.up(), entry(if error.is_empty() { 1.0 } else { 0.0 }));
+                map.insert(name, entry(value));
+            }
+        }
+
+        map
+    }
+}
+
+async fn collect_bus(conn: &zbus::Connection, key: &BusKey) -> anyhow::Result<Vec<(String, f64, i64, String)>> {
+    let proxy = Collect1Proxy::builder(conn)
+        .destination(key.bus.clone())?
+        .path(DPROM_PATH)?
+        .cache_properties(zbus::CacheProperties::No)
+        .build()
+        .await?;
+
+    Ok(proxy.collect().await?)
+}
+
+impl<'a> Drop for CollectHandle<'a> {
+    fn drop(&mut self) {
+        self.collector.buses.lock().unwrap().remove(&self.key);
+    }
+}
@@ -1,4 +1,7 @@
 use std::path::Path;
+use std::time::Duration;
+
+use serde::{de, Deserialize as _};
 use serde_derive::Deserialize;
 
 #[derive(Deserialize)]
@@ -13,6 +16,22 @@ pub struct Dbus {
     pub system: bool,
     #[serde(default = "bool_false")]
     pub session: bool,
+    #[serde(default)]
+    pub mode: Mode,
+    /// in pull mode, how long to wait on each bus's Collect at scrape time
+    #[serde(deserialize_with = "parse_duration", default = "default_collect_timeout_secs")]
+    pub collect_timeout_secs: Duration,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// cache values as publishers signal changes to them
+    #[default]
+    Push,
+    /// call Collect at scrape time on publishers that implement Collect1,
+    /// falling back to push for those that don't
+    Pull,
 }
 
 fn bool_true() -> bool {
@@ -27,18 +46,93 @@ fn bool_false() -> bool {
 pub struct Http {
     pub listen: std::net::SocketAddr,
     pub tls: Option<Tls>,
+    /// emit the time each sample was received as its timestamp in /metrics.
+    /// /federate always includes timestamps
+    #[serde(default = "bool_false")]
+    pub timestamps: bool,
+    pub staleness: Option<Staleness>,
+    /// on SIGTERM or SIGINT, how long to wait for in-flight requests to
+    /// finish before closing their connections
+    #[serde(deserialize_with = "parse_duration", default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: Duration,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
+pub struct Staleness {
+    /// series not updated for this long are considered stale. series are
+    /// only updated when their publisher signals a change, so publishers of
+    /// values which rarely change must re-signal them to stay fresh
+    #[serde(deserialize_with = "parse_duration")]
+    pub max_age_secs: Duration,
+    #[serde(default)]
+    pub action: StaleAction,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StaleAction {
+    /// omit stale series from the output
+    #[default]
+    Drop,
+    /// keep stale series, and report their age in `dprom_metric_age_seconds`
+    Flag,
+}
+
+#[derive(Deserialize, Clone)]
 pub struct Tls {
     pub cert: std::path::PathBuf,
     pub key: std::path::PathBuf,
     pub verify: Option<Verify>,
+    /// how often to check cert, key and CA files for changes. certificates
+    /// are also reloaded on SIGHUP regardless of this setting
+    #[serde(deserialize_with = "parse_duration", default = "default_reload_secs")]
+    pub reload_secs: Duration,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct Verify {
     pub ca: std::path::PathBuf,
+    /// glob patterns matched against the common name and subject alternative
+    /// names of client certificates. when empty, any client cert signed by
+    /// the CA is accepted
+    #[serde(deserialize_with = "parse_patterns", default)]
+    pub allow: Vec<glob::Pattern>,
+}
+
+const fn default_collect_timeout_secs() -> Duration {
+    Duration::from_secs(5)
+}
+
+const fn default_reload_secs() -> Duration {
+    Duration::from_secs(60)
+}
+
+const fn default_shutdown_timeout_secs() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn parse_duration<'de, D>(d: D) -> Result<Duration, D::Error>
+    where D: de::Deserializer<'de>
+{
+    let duration = f64::deserialize(d)?;
+
+    // durations are used as interval periods, which mustn't be zero, so
+    // values too small to represent are refused too:
+    match Duration::try_from_secs_f64(duration) {
+        Ok(parsed) if !parsed.is_zero() => Ok(parsed),
+        _ => Err(de::Error::invalid_value(de::Unexpected::Float(duration), &"duration must be positive")),
+    }
+}
+
+fn parse_patterns<'de, D>(d: D) -> Result<Vec<glob::Pattern>, D::Error>
+    where D: de::Deserializer<'de>
+{
+    Vec::<String>::deserialize(d)?
+        .iter()
+        .map(|pattern| glob::Pattern::new(pattern).map_err(|e| {
+            de::Error::invalid_value(de::Unexpected::Str(pattern), &e.msg)
+        }))
+        .collect()
 }
 
 pub async fn open(path: &Path) -> Result<Config, anyhow::Error> {
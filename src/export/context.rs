@@ -3,29 +3,47 @@ use std::sync::Arc;
 use zbus::names::UniqueName;
 use zbus::zvariant::OwnedObjectPath;
 
-use crate::export::metric::Export;
+use crate::export::collect::Collector;
+use crate::export::metric::{Export, Source};
+use crate::export::status::Status;
 
 #[derive(Clone)]
 pub struct Ctx {
     pub log: slog::Logger,
+    pub kind: &'static str,
     pub conn: Arc<zbus::Connection>,
     pub export: Arc<Export>,
+    pub status: Arc<Status>,
+    pub collector: Option<Arc<Collector>>,
 }
 
 impl Ctx {
-    pub fn new(log: slog::Logger, conn: Arc<zbus::Connection>, export: Arc<Export>) -> Self {
+    pub fn new(
+        log: slog::Logger,
+        kind: &'static str,
+        conn: Arc<zbus::Connection>,
+        export: Arc<Export>,
+        status: Arc<Status>,
+        collector: Option<Arc<Collector>>,
+    ) -> Self {
         Ctx {
             log,
+            kind,
             conn,
             export,
+            status,
+            collector,
         }
     }
 
     pub fn with_bus(&self, bus: UniqueName<'static>) -> BusCtx {
         BusCtx {
             log: self.log.new(slog::o!("bus" => bus.to_string())),
+            kind: self.kind,
             conn: self.conn.clone(),
             export: self.export.clone(),
+            status: self.status.clone(),
+            collector: self.collector.clone(),
             bus,
         }
     }
@@ -34,8 +52,11 @@ impl Ctx {
 #[derive(Clone)]
 pub struct BusCtx {
     pub log: slog::Logger,
+    pub kind: &'static str,
     pub conn: Arc<zbus::Connection>,
     pub export: Arc<Export>,
+    pub status: Arc<Status>,
+    pub collector: Option<Arc<Collector>>,
     pub bus: UniqueName<'static>,
 }
 
@@ -43,8 +64,11 @@ impl BusCtx {
     pub fn with_path(&self, path: OwnedObjectPath) -> PathCtx {
         PathCtx {
             log: self.log.new(slog::o!("path" => path.to_string())),
+            kind: self.kind,
             conn: self.conn.clone(),
             export: self.export.clone(),
+            status: self.status.clone(),
+            collector: self.collector.clone(),
             bus: self.bus.clone(),
             path,
         }
@@ -54,18 +78,51 @@ impl BusCtx {
 #[derive(Clone)]
 pub struct PathCtx {
     pub log: slog::Logger,
+    pub kind: &'static str,
     pub conn: Arc<zbus::Connection>,
     pub export: Arc<Export>,
+    pub status: Arc<Status>,
+    pub collector: Option<Arc<Collector>>,
     pub bus: UniqueName<'static>,
     pub path: OwnedObjectPath,
 }
 
 impl PathCtx {
     pub async fn proxy<T: From<zbus::Proxy<'static>> + zbus::ProxyDefault>(&self) -> zbus::Result<T> {
-        Ok(zbus::ProxyBuilder::new(&self.conn.clone())
+        zbus::ProxyBuilder::new(&self.conn.clone())
             .destination(self.bus.clone())?
             .path(self.path.clone())?
             .build()
-            .await?)
+            .await
+    }
+
+    /// as `proxy`, but without caching properties or subscribing to changes
+    /// in them
+    pub async fn uncached_proxy<T: From<zbus::Proxy<'static>> + zbus::ProxyDefault>(&self) -> zbus::Result<T> {
+        zbus::ProxyBuilder::new(&self.conn.clone())
+            .destination(self.bus.clone())?
+            .path(self.path.clone())?
+            .cache_properties(zbus::CacheProperties::No)
+            .build()
+            .await
+    }
+
+    /// Reads the Name property of interface `T`, failing with an unknown
+    /// dispatch error if the object doesn't implement it. Used to probe a
+    /// metric's type before building a caching proxy, as cached proxies of
+    /// interfaces an object lacks can stall the connection.
+    pub async fn metric_name<T>(&self) -> zbus::Result<String>
+        where T: From<zbus::Proxy<'static>> + zbus::ProxyDefault + AsRef<zbus::Proxy<'static>>
+    {
+        let proxy = self.uncached_proxy::<T>().await?;
+        proxy.as_ref().get_property("Name").await
+    }
+
+    pub fn source(&self) -> Source {
+        Source {
+            connection: self.kind,
+            bus: self.bus.to_string(),
+            path: self.path.to_string(),
+        }
     }
 }
@@ -1,29 +1,45 @@
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{hash_map, HashMap};
+use std::time::Duration;
 
 use futures::future::{self, Future};
 use futures::stream::{self, Stream, StreamExt, TryStreamExt, FuturesUnordered};
 use zbus::names::{BusName, ErrorName, UniqueName};
 use zbus::zvariant::OwnedObjectPath;
 
-use crate::dbus::{dprom::DProm1Proxy, gauge::Gauge1Proxy};
+use crate::dbus::{dprom::DProm1Proxy, dprom2::DProm2Proxy, gauge::Gauge1Proxy};
+use crate::dbus::{counter::Counter1Proxy, histogram::Histogram1Proxy};
+use crate::dbus::{info::Info1Proxy, native_histogram::NativeHistogram1Proxy, state_set::StateSet1Proxy};
+use crate::export::collect::Collector;
 use crate::export::config;
 use crate::export::context::{Ctx, BusCtx, PathCtx};
-use crate::export::metric::{Export, MetricName};
+use crate::export::metric::{Exemplar, Export, MetricHandle, MetricName, MetricValue, NativeHistogram};
+use crate::export::status::{BusHandle, Status};
 use crate::future::linger::{linger, Linger};
 
-pub async fn run(log: slog::Logger, export: Export, config: config::Dbus) -> anyhow::Result<()> {
+/// how long a bus has to answer the DProm2 probe, and how many times it's
+/// asked, before it's assumed not to implement DProm2
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+const PROBE_ATTEMPTS: usize = 3;
+
+pub async fn run(
+    log: slog::Logger,
+    export: Export,
+    status: Arc<Status>,
+    collector: Option<Arc<Collector>>,
+    config: config::Dbus,
+) -> anyhow::Result<()> {
     let export = Arc::new(export);
 
     let futures = FuturesUnordered::new();
 
     if config.session {
-        futures.push(linger(start_dbus(&log, export.clone(),
+        futures.push(linger(start_dbus(&log, export.clone(), status.clone(), collector.clone(),
             "session", zbus::Connection::session()).await?));
     }
 
     if config.system {
-        futures.push(linger(start_dbus(&log, export.clone(),
+        futures.push(linger(start_dbus(&log, export.clone(), status.clone(), collector.clone(),
             "system", zbus::Connection::system()).await?));
     }
 
@@ -37,12 +53,15 @@ pub async fn run(log: slog::Logger, export: Export, config: config::Dbus) -> any
     async fn start_dbus(
         log: &slog::Logger,
         export: Arc<Export>,
+        status: Arc<Status>,
+        collector: Option<Arc<Collector>>,
         kind: &'static str,
         fut: impl Future<Output = zbus::Result<zbus::Connection>>,
     ) -> anyhow::Result<impl Future<Output = anyhow::Result<()>>> {
         let log = log.new(slog::o!("dbus" => kind));
         let conn = Arc::new(fut.await?);
-        let ctx = Ctx::new(log, conn, export.clone());
+        status.connection(kind);
+        let ctx = Ctx::new(log, kind, conn, export, status, collector);
         Ok(run_top(ctx))
     }
 }
@@ -93,6 +112,8 @@ async fn run_top(ctx: Ctx) -> anyhow::Result<()> {
                 (name, linger(bus_task(ctx)))
             }));
 
+    ctx.status.set_ready(ctx.kind);
+
     // process name events:
     futures::pin_mut!(name_events);
     while let Some(event) = name_events.next().await {
@@ -110,7 +131,9 @@ async fn run_top(ctx: Ctx) -> anyhow::Result<()> {
     return Ok(());
 
     async fn bus_task(ctx: BusCtx) {
-        match run_bus(ctx.clone()).await {
+        let handle = ctx.status.bus(ctx.kind, ctx.bus.to_string());
+
+        match run_bus(ctx.clone(), &handle).await {
             Ok(()) => {}
             Err(e) => {
                 let unknown_dispatch = e.downcast_ref::<zbus::Error>()
@@ -119,13 +142,19 @@ async fn run_top(ctx: Ctx) -> anyhow::Result<()> {
 
                 if !unknown_dispatch {
                     slog::error!(ctx.log, "bus error: {:?}", e);
+                    ctx.status.error(format!("{} bus {}: {:?}", ctx.kind, ctx.bus, e));
                 }
             }
         }
     }
 }
 
-async fn run_bus(ctx: BusCtx) -> anyhow::Result<()> {
+async fn run_bus(ctx: BusCtx, handle: &BusHandle<'_>) -> anyhow::Result<()> {
+    let None = run_bus_collected(&ctx, handle).await? else { return Ok(()); };
+
+    // prefer batched value updates where the publisher supports them:
+    let None = run_bus_batched(&ctx, handle).await? else { return Ok(()); };
+
     let dprom = DProm1Proxy::builder(&ctx.conn)
         .destination(ctx.bus.clone())?
         .path("/org/hails/dprom")?
@@ -139,6 +168,8 @@ async fn run_bus(ctx: BusCtx) -> anyhow::Result<()> {
     slog::debug!(ctx.log, "watching bus");
 
     stream.try_fold(HashMap::new(), |mut tasks, metric_paths| {
+        handle.set_metrics(metric_paths.iter().map(|path| path.to_string()).collect());
+
         let new_tasks = metric_paths.into_iter()
             .map(|path| {
                 let task = tasks.remove(&path).unwrap_or_else(|| {
@@ -168,20 +199,254 @@ async fn run_bus(ctx: BusCtx) -> anyhow::Result<()> {
         Ok(stream::once(future::ok(metric_paths))
             .chain(metrics_events))
     }
+}
 
-    async fn metric_task(ctx: PathCtx) {
-        slog::debug!(ctx.log, "watching metric");
-        match run_metric(ctx.clone()).await {
-            Ok(()) => {}
-            Err(e) => {
-                slog::error!(ctx.log, "error watching metric: {:?}", e);
+/// In pull mode, registers buses implementing Collect1 to be sampled at
+/// scrape time, and keeps them registered until the bus goes away. Collect1
+/// only carries gauges, so other metrics the bus lists in DProm1 are watched
+/// as in push mode. Returns `None` when not in pull mode or the bus does not
+/// implement Collect1.
+async fn run_bus_collected(ctx: &BusCtx, handle: &BusHandle<'_>) -> anyhow::Result<Option<()>> {
+    let Some(collector) = &ctx.collector else { return Ok(None) };
+
+    let supported = protect_unknown_dispatch(collector.supports(&ctx.conn, ctx.bus.clone()).await)?;
+    if supported != Some(true) {
+        return Ok(None);
+    }
+
+    slog::debug!(ctx.log, "collecting from bus at scrape time");
+
+    // bus tasks are dropped when their bus goes away, taking the
+    // registration with them:
+    let _registration = collector.register(ctx.kind, ctx.conn.clone(), ctx.bus.clone());
+
+    let dprom = DProm1Proxy::builder(&ctx.conn)
+        .destination(ctx.bus.clone())?
+        .path("/org/hails/dprom")?
+        .build()
+        .await?;
+
+    // open receive stream first to prevent race
+    let metrics_events = dprom.receive_metrics_changed().await
+        .then(|change| async move { change.get().await });
+
+    let Some(metric_paths) = protect_unknown_dispatch(dprom.metrics().await)? else {
+        // nothing to watch besides what's collected:
+        handle.set_metrics(Vec::new());
+        future::pending::<()>().await;
+        return Ok(Some(()));
+    };
+
+    let stream = stream::once(future::ok(metric_paths)).chain(metrics_events);
+    futures::pin_mut!(stream);
+
+    // gauges are left to Collect1, and have no task:
+    let mut tasks = HashMap::<OwnedObjectPath, Option<Linger<()>>>::new();
+
+    while let Some(metric_paths) = stream.next().await {
+        let metric_paths = metric_paths?;
+
+        handle.set_metrics(metric_paths.iter().map(|path| path.to_string()).collect());
+
+        tasks.retain(|path, _| metric_paths.contains(path));
+
+        for path in metric_paths {
+            if let hash_map::Entry::Vacant(entry) = tasks.entry(path.clone()) {
+                let path_ctx = ctx.with_path(path);
+                let task = match is_gauge(&path_ctx).await {
+                    Ok(true) => None,
+                    Ok(false) => Some(linger(metric_task(path_ctx))),
+                    Err(e) => {
+                        slog::error!(path_ctx.log, "error reading metric: {:?}", e);
+                        ctx.status.error(format!("{} bus {} {}: {:?}",
+                            ctx.kind, ctx.bus, path_ctx.path.as_str(), e));
+                        None
+                    }
+                };
+                entry.insert(task);
+            }
+        }
+    }
+
+    return Ok(Some(()));
+
+    async fn is_gauge(ctx: &PathCtx) -> zbus::Result<bool> {
+        Ok(protect_unknown_dispatch(ctx.metric_name::<Gauge1Proxy>().await)?.is_some())
+    }
+}
+
+/// Gauges on a DProm2 bus take their values from ValuesChanged, other
+/// metric types are watched individually as with DProm1
+enum BatchedMetric<'a> {
+    Gauge {
+        name: MetricName,
+        metric: MetricHandle<'a>,
+        /// only for gauges implementing Error
+        up: Option<MetricHandle<'a>>,
+    },
+    Watched { _task: Linger<()> },
+    Failed,
+}
+
+enum BatchEvent {
+    Metrics(Vec<OwnedObjectPath>),
+    /// value, timestamp and error of each gauge, timestamps being 0 and
+    /// errors empty for gauges without them
+    Values(Vec<(OwnedObjectPath, f64, i64, String)>),
+}
+
+/// Watches a bus through the batched DProm2 interface, where values for all
+/// gauges arrive in a single ValuesChanged signal rather than a
+/// PropertiesChanged signal per Gauge1 object. Returns `None` if the bus does
+/// not implement DProm2.
+async fn run_bus_batched(ctx: &BusCtx, handle: &BusHandle<'_>) -> anyhow::Result<Option<()>> {
+    // probe for DProm2 before subscribing to ValuesChanged. a signal stream
+    // left unpolled stalls the whole connection once its queue fills, and
+    // peers that serve no objects, our own connection included, never reply
+    let probe = || async {
+        let probe = DProm2Proxy::builder(&ctx.conn)
+            .destination(ctx.bus.clone())?
+            .path("/org/hails/dprom")?
+            .cache_properties(zbus::CacheProperties::No)
+            .build()
+            .await?;
+
+        probe.metrics().await
+    };
+
+    // zbus peers drop calls which arrive before their objects are served,
+    // as happens to buses probed the moment they connect, so it's asked
+    // again:
+    let mut probed = None;
+    for _ in 0..PROBE_ATTEMPTS {
+        if let Ok(result) = tokio::time::timeout(PROBE_TIMEOUT, probe()).await {
+            probed = Some(result);
+            break;
+        }
+    }
+
+    let Some(probed) = probed else { return Ok(None) };
+
+    if protect_unknown_dispatch(probed)?.is_none() {
+        return Ok(None);
+    }
+
+    let dprom = DProm2Proxy::builder(&ctx.conn)
+        .destination(ctx.bus.clone())?
+        .path("/org/hails/dprom")?
+        .build()
+        .await?;
+
+    // open receive streams before reading current state to prevent race
+    let values_events = dprom.receive_values_changed().await?
+        .map(|signal| {
+            let values = signal.args()?.values.into_iter()
+                .map(|(path, value, timestamp, error)| {
+                    (OwnedObjectPath::from(path), value, timestamp, error.to_owned())
+                })
+                .collect();
+
+            Ok::<_, zbus::Error>(BatchEvent::Values(values))
+        });
+
+    let metrics_events = dprom.receive_metrics_changed().await
+        .then(|change| async move { change.get().await.map(BatchEvent::Metrics) });
+
+    let Some(metric_paths) = protect_unknown_dispatch(dprom.metrics().await)? else { return Ok(None) };
+
+    slog::debug!(ctx.log, "watching bus (batched)");
+
+    let events = stream::once(future::ok(BatchEvent::Metrics(metric_paths)))
+        .chain(stream::select(values_events, metrics_events));
+    futures::pin_mut!(events);
+
+    let mut metrics = HashMap::<OwnedObjectPath, BatchedMetric<'_>>::new();
+
+    while let Some(event) = events.next().await {
+        let values = match event? {
+            BatchEvent::Metrics(metric_paths) => {
+                handle.set_metrics(metric_paths.iter().map(|path| path.to_string()).collect());
+
+                metrics.retain(|path, _| metric_paths.contains(path));
+
+                for path in metric_paths {
+                    if let hash_map::Entry::Vacant(entry) = metrics.entry(path.clone()) {
+                        let path_ctx = ctx.with_path(path);
+                        let metric = match gauge_info(&path_ctx).await {
+                            Ok(Some((name, has_error))) => BatchedMetric::Gauge {
+                                metric: ctx.export.metric(name.clone(), path_ctx.source()),
+                                up: has_error.then(|| ctx.export.metric(name.up(), path_ctx.source())),
+                                name,
+                            },
+                            Ok(None) => BatchedMetric::Watched { _task: linger(metric_task(path_ctx)) },
+                            Err(e) => {
+                                slog::error!(path_ctx.log, "error reading metric: {:?}", e);
+                                ctx.status.error(format!("{} bus {} {}: {:?}",
+                                    ctx.kind, ctx.bus, path_ctx.path.as_str(), e));
+                                BatchedMetric::Failed
+                            }
+                        };
+                        entry.insert(metric);
+                    }
+                }
+
+                // fetch a fresh snapshot so new metrics get their values
+                // without waiting for them to next change
+                dprom.get_values().await?
+            }
+            BatchEvent::Values(values) => values,
+        };
+
+        for (path, value, timestamp, error) in values {
+            if let Some(BatchedMetric::Gauge { name, metric, up }) = metrics.get(&path) {
+                let timestamp = (timestamp != 0).then_some(timestamp);
+
+                if let Some(up) = up {
+                    if !error.is_empty() {
+                        slog::debug!(ctx.log, "{} failed: {}", name, error);
+                    }
+
+                    up.gauge(if error.is_empty() { 1.0 } else { 0.0 }, timestamp).await;
+                }
+
+                slog::info!(ctx.log, "{} = {}", name, value);
+                metric.gauge(value, timestamp).await;
             }
         }
     }
+
+    return Ok(Some(()));
+
+    /// the gauge's name, and whether it implements Error. `None` if the
+    /// metric is not a gauge. errors are logged by the caller rather than
+    /// returned so that one bad metric does not stop the rest of the bus
+    /// from being watched
+    async fn gauge_info(ctx: &PathCtx) -> zbus::Result<Option<(MetricName, bool)>> {
+        let gauge = ctx.uncached_proxy::<Gauge1Proxy>().await?;
+        let Some(name) = protect_unknown_dispatch(gauge.name().await)? else { return Ok(None) };
+        let has_error = protect_unknown_dispatch(gauge.error().await)?.is_some();
+        Ok(Some((MetricName::from(name), has_error)))
+    }
+}
+
+async fn metric_task(ctx: PathCtx) {
+    slog::debug!(ctx.log, "watching metric");
+    match run_metric(ctx.clone()).await {
+        Ok(()) => {}
+        Err(e) => {
+            slog::error!(ctx.log, "error watching metric: {:?}", e);
+            ctx.status.error(format!("{} bus {} {}: {:?}", ctx.kind, ctx.bus, ctx.path.as_str(), e));
+        }
+    }
 }
 
 async fn run_metric(ctx: PathCtx) -> anyhow::Result<()> {
     let None = run_gauge(&ctx).await? else { return Ok(()); };
+    let None = run_counter(&ctx).await? else { return Ok(()); };
+    let None = run_histogram(&ctx).await? else { return Ok(()); };
+    let None = run_native_histogram(&ctx).await? else { return Ok(()); };
+    let None = run_info(&ctx).await? else { return Ok(()); };
+    let None = run_state_set(&ctx).await? else { return Ok(()); };
     // more types when implemented will follow here
 
     slog::warn!(ctx.log, "unknown metric type");
@@ -198,25 +463,235 @@ async fn run_gauge(ctx: &PathCtx) -> anyhow::Result<Option<()>> {
 
     let value = gauge.value().await?;
 
+    // Timestamp and Error are optional, only read them if the publisher
+    // implements them:
+    let has_timestamp = protect_unknown_dispatch(gauge.timestamp().await)?.is_some();
+    let has_error = protect_unknown_dispatch(gauge.error().await)?.is_some();
+
     let stream = stream::once(future::ready(Ok(value))).chain(stream);
     futures::pin_mut!(stream);
 
-    let metric = ctx.export.metric(name.clone());
+    let metric = ctx.export.metric(name.clone(), ctx.source());
+    let up = has_error.then(|| ctx.export.metric(name.up(), ctx.source()));
 
     while let Some(result) = stream.next().await {
         let value = result?;
+
+        let timestamp = match has_timestamp {
+            true => Some(gauge.timestamp().await?),
+            false => None,
+        };
+
+        if let Some(up) = &up {
+            let error = gauge.error().await?;
+
+            if !error.is_empty() {
+                slog::debug!(ctx.log, "{} failed: {}", name, error);
+            }
+
+            up.gauge(if error.is_empty() { 1.0 } else { 0.0 }, timestamp).await;
+        }
+
         slog::info!(ctx.log, "{} = {}", name, value);
-        metric.gauge(value).await;
+        metric.gauge(value, timestamp).await;
     }
 
     return Ok(Some(()));
 
     /// responsible for checking gauge type
-    async fn access(ctx: &PathCtx) -> zbus::Result<Option<(MetricName, Gauge1Proxy)>> {
-        let gauge = ctx.proxy::<Gauge1Proxy>().await?;
-        Ok(protect_unknown_dispatch(gauge.name().await)?
-            .map(|name| (MetricName::from(name), gauge)))
+    async fn access(ctx: &PathCtx) -> zbus::Result<Option<(MetricName, Gauge1Proxy<'_>)>> {
+        let Some(name) = protect_unknown_dispatch(ctx.metric_name::<Gauge1Proxy>().await)? else { return Ok(None) };
+        Ok(Some((MetricName::from(name), ctx.proxy::<Gauge1Proxy>().await?)))
+    }
+}
+
+async fn run_counter(ctx: &PathCtx) -> anyhow::Result<Option<()>> {
+    let Some(name) = protect_unknown_dispatch(ctx.metric_name::<Counter1Proxy>().await)? else { return Ok(None) };
+    let name = MetricName::from(name);
+    let counter = ctx.proxy::<Counter1Proxy>().await?;
+
+    // open streams before reading first value to avoid race. Error can
+    // change without Value, so changes to either cause both to be read
+    // from the proxy's cache
+    let changes = stream::select(
+        counter.receive_value_changed().await.map(|_| ()),
+        counter.receive_error_changed().await.map(|_| ()));
+
+    // Timestamp, Exemplar and Error are optional, only read them if the
+    // publisher implements them:
+    let has_timestamp = protect_unknown_dispatch(counter.timestamp().await)?.is_some();
+    let has_exemplar = protect_unknown_dispatch(counter.exemplar().await)?.is_some();
+    let has_error = protect_unknown_dispatch(counter.error().await)?.is_some();
+
+    let stream = stream::once(future::ready(())).chain(changes);
+    futures::pin_mut!(stream);
+
+    let metric = ctx.export.metric(name.clone(), ctx.source());
+    let up = has_error.then(|| ctx.export.metric(name.up(), ctx.source()));
+
+    while stream.next().await.is_some() {
+        let value = counter.value().await?;
+
+        let timestamp = match has_timestamp {
+            true => Some(counter.timestamp().await?),
+            false => None,
+        };
+
+        if let Some(up) = &up {
+            let error = counter.error().await?;
+            up.gauge(if error.is_empty() { 1.0 } else { 0.0 }, timestamp).await;
+
+            // Value may never have been sampled, and would look like a
+            // reset if exported:
+            if !error.is_empty() {
+                slog::debug!(ctx.log, "{} failed: {}", name, error);
+                continue;
+            }
+        }
+
+        let exemplar = match has_exemplar {
+            true => Exemplar::from_dbus(counter.exemplar().await?),
+            false => None,
+        };
+
+        slog::info!(ctx.log, "{} = {}", name, value);
+        metric.measure(MetricValue::Counter { value, exemplar }, timestamp).await;
+    }
+
+    Ok(Some(()))
+}
+
+async fn run_histogram(ctx: &PathCtx) -> anyhow::Result<Option<()>> {
+    let Some(name) = protect_unknown_dispatch(ctx.metric_name::<Histogram1Proxy>().await)? else { return Ok(None) };
+    let name = MetricName::from(name);
+    let histogram = ctx.proxy::<Histogram1Proxy>().await?;
+
+    // open stream before reading first value to avoid race. Count changes
+    // with every observation, and the other properties are sent in the same
+    // signal, so they can be read from the proxy's cache
+    let changes = histogram.receive_count_changed().await.map(|_| ());
+    let stream = stream::once(future::ready(())).chain(changes);
+    futures::pin_mut!(stream);
+
+    // Exemplars are optional, only read them if the publisher implements them:
+    let has_exemplars = protect_unknown_dispatch(histogram.exemplars().await)?.is_some();
+
+    let metric = ctx.export.metric(name.clone(), ctx.source());
+
+    while stream.next().await.is_some() {
+        let exemplars = match has_exemplars {
+            true => histogram.exemplars().await?.into_iter()
+                .filter_map(Exemplar::from_dbus)
+                .collect(),
+            false => Vec::new(),
+        };
+
+        let value = MetricValue::Histogram {
+            buckets: histogram.buckets().await?,
+            sum: histogram.sum().await?,
+            count: histogram.count().await?,
+            exemplars,
+        };
+
+        slog::info!(ctx.log, "{} = {}", name, value);
+        metric.measure(value, None).await;
+    }
+
+    Ok(Some(()))
+}
+
+async fn run_native_histogram(ctx: &PathCtx) -> anyhow::Result<Option<()>> {
+    let Some(name) = protect_unknown_dispatch(ctx.metric_name::<NativeHistogram1Proxy>().await)? else { return Ok(None) };
+    let name = MetricName::from(name);
+    let histogram = ctx.proxy::<NativeHistogram1Proxy>().await?;
+
+    // as with Histogram1, only Count is watched and the rest read from the
+    // proxy's cache
+    let changes = histogram.receive_count_changed().await.map(|_| ());
+    let stream = stream::once(future::ready(())).chain(changes);
+    futures::pin_mut!(stream);
+
+    let metric = ctx.export.metric(name.clone(), ctx.source());
+
+    while stream.next().await.is_some() {
+        let value = NativeHistogram {
+            schema: histogram.schema().await?,
+            zero_threshold: histogram.zero_threshold().await?,
+            zero_count: histogram.zero_count().await?,
+            count: histogram.count().await?,
+            sum: histogram.sum().await?,
+            positive_spans: histogram.positive_spans().await?,
+            positive_deltas: histogram.positive_deltas().await?,
+            negative_spans: histogram.negative_spans().await?,
+            negative_deltas: histogram.negative_deltas().await?,
+        };
+
+        // an invalid histogram would fail the whole scrape, so skip it
+        // rather than passing it on:
+        if let Err(e) = value.validate() {
+            slog::warn!(ctx.log, "invalid native histogram: {}", e);
+            continue;
+        }
+
+        let value = MetricValue::NativeHistogram(value);
+        slog::info!(ctx.log, "{} = {}", name, value);
+        metric.measure(value, None).await;
+    }
+
+    Ok(Some(()))
+}
+
+async fn run_info(ctx: &PathCtx) -> anyhow::Result<Option<()>> {
+    let Some(name) = protect_unknown_dispatch(ctx.metric_name::<Info1Proxy>().await)? else { return Ok(None) };
+    let name = MetricName::from(name);
+    let info = ctx.proxy::<Info1Proxy>().await?;
+
+    // open stream before reading first value to avoid race
+    let stream = info.receive_labels_changed().await
+        .then(|change| async move { change.get().await });
+
+    let labels = info.labels().await?;
+
+    let stream = stream::once(future::ready(Ok(labels))).chain(stream);
+    futures::pin_mut!(stream);
+
+    let metric = ctx.export.metric(name.clone(), ctx.source());
+
+    while let Some(result) = stream.next().await {
+        let value = MetricValue::Info(result?.into_iter().collect());
+        slog::info!(ctx.log, "{} = {}", name, value);
+        metric.measure(value, None).await;
+    }
+
+    Ok(Some(()))
+}
+
+async fn run_state_set(ctx: &PathCtx) -> anyhow::Result<Option<()>> {
+    let Some(name) = protect_unknown_dispatch(ctx.metric_name::<StateSet1Proxy>().await)? else { return Ok(None) };
+    let name = MetricName::from(name);
+    let state_set = ctx.proxy::<StateSet1Proxy>().await?;
+
+    // open streams before reading first value to avoid race. either property
+    // changing causes both to be read again from the proxy's cache
+    let changes = stream::select(
+        state_set.receive_states_changed().await.map(|_| ()),
+        state_set.receive_state_changed().await.map(|_| ()));
+
+    let stream = stream::once(future::ready(())).chain(changes);
+    futures::pin_mut!(stream);
+
+    let metric = ctx.export.metric(name.clone(), ctx.source());
+
+    while stream.next().await.is_some() {
+        let states = state_set.states().await?;
+        let state = state_set.state().await?;
+
+        let value = MetricValue::StateSet { states, state };
+        slog::info!(ctx.log, "{} = {}", name, value);
+        metric.measure(value, None).await;
     }
+
+    Ok(Some(()))
 }
 
 fn protect_unknown_dispatch<T>(result: Result<T, zbus::Error>)
@@ -234,13 +709,11 @@ fn is_unknown_dispatch_error(err: &zbus::Error) -> bool {
     use zbus::fdo::Error;
 
     match err {
-        FDO(err) => match **err {
+        FDO(err) => matches!(**err,
             | Error::UnknownObject(_)
             | Error::UnknownMethod(_)
             | Error::UnknownInterface(_)
-            | Error::UnknownProperty(_) => true,
-            _ => false,
-        }
+            | Error::UnknownProperty(_)),
         zbus::Error::MethodError(name, _desc, _msg)
             if is_unknown_dispatch_error_name(name.inner()) => true,
         _ => false,
@@ -248,11 +721,9 @@ fn is_unknown_dispatch_error(err: &zbus::Error) -> bool {
 }
 
 fn is_unknown_dispatch_error_name(err: &ErrorName) -> bool {
-    match err.as_str() {
+    matches!(err.as_str(),
         | "org.freedesktop.DBus.Error.UnknownInterface"
         | "org.freedesktop.DBus.Error.UnknownObject"
         | "org.freedesktop.DBus.Error.UnknownMethod"
-        | "org.freedesktop.DBus.Error.UnknownProperty" => true,
-        _ => false,
-    }
+        | "org.freedesktop.DBus.Error.UnknownProperty")
 }
@@ -0,0 +1,215 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::export::config::{self, StaleAction};
+use crate::export::live::MetricMap;
+use crate::export::metric::{Exemplar, MetricValue};
+use crate::export::selector::SeriesFilter;
+use crate::export::{proto, text};
+
+/// Exposition formats, negotiated from the scraper's Accept header.
+/// OpenMetrics adds exemplars and native info and stateset types, protobuf
+/// is the only one able to carry native histograms.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Prometheus,
+    OpenMetrics,
+    Protobuf,
+}
+
+impl Format {
+    /// the supported format with the highest quality in `accept`, or the
+    /// Prometheus text format if there is none
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        let Some(accept) = accept else { return Format::Prometheus };
+
+        // min_by returns the first of equals, so ties go to the format the
+        // scraper listed first:
+        accept.split(',')
+            .filter_map(media_range)
+            .filter(|(_, quality)| *quality > 0.0)
+            .min_by(|(_, a), (_, b)| b.total_cmp(a))
+            .map(|(format, _)| format)
+            .unwrap_or(Format::Prometheus)
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Prometheus => "text/plain; version=0.0.4; charset=utf-8",
+            Format::OpenMetrics => "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            Format::Protobuf => "application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; encoding=delimited",
+        }
+    }
+}
+
+fn media_range(range: &str) -> Option<(Format, f64)> {
+    let mut params = range.split(';').map(str::trim);
+    let media_type = params.next()?;
+
+    let mut quality = 1.0;
+    let mut proto = None;
+    let mut encoding = None;
+
+    for param in params {
+        match param.split_once('=') {
+            Some(("q", value)) => { quality = value.parse().unwrap_or(0.0); }
+            Some(("proto", value)) => { proto = Some(value); }
+            Some(("encoding", value)) => { encoding = Some(value); }
+            _ => {}
+        }
+    }
+
+    let format = match media_type {
+        "application/openmetrics-text" => Format::OpenMetrics,
+        "application/vnd.google.protobuf"
+            if proto == Some("io.prometheus.client.MetricFamily") && encoding == Some("delimited")
+            => Format::Protobuf,
+        "text/plain" | "text/*" | "*/*" => Format::Prometheus,
+        _ => { return None; }
+    };
+
+    Some((format, quality))
+}
+
+/// Metrics chosen for a scrape, under the names they are exposed as
+pub struct Selection {
+    pub metrics: Vec<Selected>,
+    /// flagged metrics older than the configured staleness limit, and their
+    /// age
+    pub stale: Vec<(String, Duration)>,
+}
+
+pub struct Selected {
+    pub name: String,
+    pub value: MetricValue,
+    /// unix milliseconds
+    pub timestamp: Option<i64>,
+    /// when the series was created or last reset, unix milliseconds
+    pub created: Option<i64>,
+}
+
+impl Selection {
+    /// every metric in `map` matched by `filter`
+    pub fn new(
+        map: &MetricMap,
+        filter: &SeriesFilter,
+        timestamps: bool,
+        staleness: Option<&config::Staleness>,
+    ) -> Self {
+        let mut selection = Selection {
+            metrics: Vec::new(),
+            stale: Vec::new(),
+        };
+
+        let now = SystemTime::now();
+
+        for (name, entry) in map.iter() {
+            let exposed = exposed_name(name.as_str(), &entry.value);
+
+            if !matches(filter, &exposed, &entry.value) {
+                continue;
+            }
+
+            if let Some(staleness) = staleness {
+                let age = now.duration_since(entry.updated).unwrap_or_default();
+
+                if age > staleness.max_age_secs {
+                    match staleness.action {
+                        StaleAction::Drop => { continue; }
+                        StaleAction::Flag => { selection.stale.push((exposed.clone(), age)); }
+                    }
+                }
+            }
+
+            // publisher supplied timestamps are always emitted, otherwise
+            // fall back to when we received the sample if configured:
+            let timestamp = entry.timestamp
+                .or_else(|| timestamps.then(|| unix_millis(entry.updated)));
+
+            selection.metrics.push(Selected {
+                name: exposed,
+                value: entry.value.clone(),
+                timestamp,
+                created: Some(unix_millis(entry.created)),
+            });
+        }
+
+        selection
+    }
+
+    /// adds a counter maintained by the exporter itself
+    pub fn counter(&mut self, name: &str, value: u64) {
+        self.metrics.push(Selected {
+            name: name.to_owned(),
+            value: MetricValue::Counter { value, exemplar: None },
+            timestamp: None,
+            created: None,
+        });
+    }
+
+    pub fn render(&self, format: Format) -> Vec<u8> {
+        match format {
+            Format::Prometheus => text::render(self, false).into_bytes(),
+            Format::OpenMetrics => text::render(self, true).into_bytes(),
+            Format::Protobuf => proto::render(self),
+        }
+    }
+}
+
+/// whether `filter` selects the metric `name`, matched by the name and
+/// labels it's exposed with rather than its name on the bus
+pub fn selects(filter: &SeriesFilter, name: &str, value: &MetricValue) -> bool {
+    matches(filter, &exposed_name(name, value), value)
+}
+
+fn matches(filter: &SeriesFilter, exposed: &str, value: &MetricValue) -> bool {
+    match value {
+        MetricValue::Info(labels) => filter.matches_series(exposed, |label| {
+            labels.iter()
+                .find(|(name, _)| label_name(name) == label)
+                .map(|(_, value)| value.as_str())
+        }),
+        _ => filter.matches(exposed),
+    }
+}
+
+/// info metrics are exposed with an `_info` suffix, which publishers may
+/// already have included in their name
+fn exposed_name(name: &str, value: &MetricValue) -> String {
+    match value {
+        MetricValue::Info(_) if !name.ends_with("_info") => format!("{}_info", name),
+        _ => name.to_owned(),
+    }
+}
+
+/// replaces characters not allowed in label names with underscores
+pub fn label_name(name: &str) -> String {
+    let mut label = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect::<String>();
+
+    if label.is_empty() || label.starts_with(|c: char| c.is_ascii_digit()) {
+        label.insert(0, '_');
+    }
+
+    label
+}
+
+/// the most recent exemplar falling in the histogram bucket bounded by `le`
+pub fn bucket_exemplar<'a>(buckets: &[(f64, u64)], exemplars: &'a [Exemplar], le: f64) -> Option<&'a Exemplar> {
+    exemplars.iter()
+        .rev()
+        .find(|exemplar| {
+            let bound = buckets.iter()
+                .map(|(le, _)| *le)
+                .find(|le| exemplar.value <= *le)
+                .unwrap_or(f64::INFINITY);
+
+            bound == le
+        })
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default()
+}
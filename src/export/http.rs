@@ -1,123 +1,228 @@
-use std::collections::BTreeMap;
-use std::fmt::Write;
-use std::sync::{Arc, RwLock};
-
-use futures::stream::{Stream, StreamExt};
+use std::fmt::Display;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use anyhow::Context;
+use futures::future::{self, Either, FutureExt};
+use futures::stream::Stream;
+use tokio::net::TcpListener;
 use warp::Filter;
-
-use crate::export::metric::{MetricName, MetricValue, Record};
-use crate::export::config;
-
-const UPDATE_CHUNK_SIZE: usize = 64; // chosen arbritrarily
+use warp::http::StatusCode;
+use warp::reply::{Reply, Response};
+
+use crate::export::collect::Collector;
+use crate::export::live::{LiveMetrics, MetricMap};
+use crate::export::metric::Record;
+use crate::export::selector::SeriesFilter;
+use crate::export::status::Status;
+use crate::export::format::{Format, Selection};
+use crate::export::{api, config, page, tls};
+use crate::shutdown::Shutdown;
 
 pub async fn run(
     log: slog::Logger,
     metric_stream: impl Stream<Item = Record> + Send + 'static,
+    status: Arc<Status>,
+    collector: Option<Arc<Collector>>,
+    shutdown: Shutdown,
     config: config::Http,
 ) -> Result<(), anyhow::Error> {
     let live = Arc::new(LiveMetrics::new(log.clone(), metric_stream));
 
-    let root = warp::path!().then(root);
+    // only rendered when client verification is enabled:
+    let tls_stats = config.tls.as_ref()
+        .and_then(|tls| tls.verify.as_ref())
+        .map(|_| Arc::new(tls::Stats::default()));
+
+    let root = warp::path!().map({
+        let live = live.clone();
+        let status = status.clone();
+        move || warp::reply::html(page::status(&live, &status))
+    });
+
+    let healthy = warp::path!("-" / "healthy")
+        .map(|| "Healthy.\n");
+
+    let ready = warp::path!("-" / "ready").map({
+        let status = status.clone();
+        move || ready(&status)
+    });
+
+    let exposition = Arc::new(Exposition {
+        timestamps: config.timestamps,
+        staleness: config.staleness.clone(),
+        collector,
+    });
+
+    let metrics = warp::path!("metrics").and(query()).and(negotiate()).then({
+        let live = live.clone();
+        let exposition = exposition.clone();
+        let tls_stats = tls_stats.clone();
+        move |query: String, format: Format| {
+            let live = live.clone();
+            let exposition = exposition.clone();
+            let tls_stats = tls_stats.clone();
+            async move { metrics(&live, &exposition, tls_stats.as_deref(), &query, format).await }
+        }
+    });
 
-    let metrics = warp::path!("metrics").then(move || {
+    let federate = warp::path!("federate").and(query()).and(negotiate()).then({
         let live = live.clone();
-        async move { metrics(&live).await }
+        let exposition = Arc::new(Exposition {
+            timestamps: true,
+            ..(*exposition).clone()
+        });
+        move |query: String, format: Format| {
+            let live = live.clone();
+            let exposition = exposition.clone();
+            async move { federate(&live, &exposition, &query, format).await }
+        }
     });
 
-    let routes = warp::get().and(root.or(metrics));
+    let routes = warp::get().and(root
+        .or(healthy)
+        .or(ready)
+        .or(metrics)
+        .or(federate)
+        .or(api::routes(live, status, shutdown.clone())));
 
     let server = warp::serve(routes);
 
-    match config.tls {
+    // stops accepting connections on shutdown, and completes once those
+    // already open have been served:
+    let graceful = shutdown.clone().wait();
+
+    let server = match config.tls {
         None => {
-            server.run(config.listen).await;
+            let (_, server) = server.try_bind_with_graceful_shutdown(config.listen, graceful)
+                .with_context(|| format!("binding {}", config.listen))?;
+
+            Either::Left(server)
         }
         Some(tls) => {
-            let server = server
-                .tls()
-                .key_path(tls.key)
-                .cert_path(tls.cert);
-
-            match tls.verify {
-                Some(verify) => {
-                    let server = server
-                        .client_auth_required_path(verify.ca);
-
-                    server.run(config.listen).await;
-                }
-                None => {
-                    server.run(config.listen).await;
-                }
-            }
+            let server_config = tls::watch(log.clone(), tls.clone()).await?;
+
+            let allow = Arc::new(tls.verify.map(|verify| verify.allow).unwrap_or_default());
+
+            let listener = TcpListener::bind(config.listen).await
+                .with_context(|| format!("binding {}", config.listen))?;
+
+            let tls_stats = tls_stats.unwrap_or_default();
+
+            let incoming = tls::incoming(log.clone(), listener, server_config, allow, tls_stats);
+            Either::Right(server.serve_incoming_with_graceful_shutdown(incoming, graceful))
+        }
+    };
+
+    futures::pin_mut!(server);
+
+    if let Either::Left(((), server)) = future::select(shutdown.wait().boxed(), server).await {
+        let timeout = config.shutdown_timeout_secs;
+
+        if tokio::time::timeout(timeout, server).await.is_err() {
+            slog::warn!(log, "in-flight requests still running after {:?}, closing them", timeout);
         }
     }
 
     Ok(())
 }
 
-async fn root() -> impl warp::Reply {
-    let version = env!("CARGO_PKG_VERSION");
-    warp::reply::html(format!("<pre>dprom-export {version}\n\n<a href=\"/metrics\">/metrics</a>\n</pre>\n"))
+/// ready once at least one configured bus has completed its initial scan
+fn ready(status: &Status) -> Response {
+    if status.is_ready() {
+        "Ready.\n".into_response()
+    } else {
+        warp::reply::with_status("Not ready.\n", StatusCode::SERVICE_UNAVAILABLE).into_response()
+    }
 }
 
-async fn metrics(live: &LiveMetrics) -> String {
-    let mut output = String::new();
-
-    for (name, value) in live.read().iter() {
-        let type_ = match value {
-            MetricValue::Gauge(_) => "gauge"
-        };
-
-        let _ = write!(&mut output, "# TYPE {} {}\n", name, type_);
-        let _ = write!(&mut output, "{} {}\n", name, value);
-    }
+/// raw query string, or empty if the request has none
+pub fn query() -> impl Filter<Extract = (String,), Error = std::convert::Infallible> + Clone {
+    warp::query::raw()
+        .or(warp::any().map(String::new))
+        .unify()
+}
 
-    output
+/// exposition format negotiated from the Accept header
+fn negotiate() -> impl Filter<Extract = (Format,), Error = std::convert::Infallible> + Clone {
+    warp::header::optional::<String>("accept")
+        .or(warp::any().map(|| None))
+        .unify()
+        .map(|accept: Option<String>| Format::from_accept(accept.as_deref()))
 }
 
+/// options controlling how series are gathered and rendered in the text
+/// exposition format
 #[derive(Clone)]
-struct LiveMetrics {
-    map: Arc<RwLock<MetricMap>>,
+struct Exposition {
+    timestamps: bool,
+    staleness: Option<config::Staleness>,
+    /// in pull mode, buses to sample at scrape time
+    collector: Option<Arc<Collector>>,
 }
 
-// btree to keep it nicely sorted for output :)
-type MetricMap = BTreeMap<MetricName, MetricValue>;
-
-impl LiveMetrics {
-    pub fn new(log: slog::Logger, stream: impl Stream<Item = Record> + Send + 'static) -> Self {
-        let map = Arc::new(RwLock::new(MetricMap::default()));
+/// current values of all metrics, including those collected from pull mode
+/// buses
+async fn snapshot(live: &LiveMetrics, exposition: &Exposition) -> MetricMap {
+    let collected = match &exposition.collector {
+        Some(collector) => collector.collect().await,
+        None => MetricMap::new(),
+    };
+
+    let mut map = live.read().clone();
+    map.extend(collected);
+    map
+}
 
-        tokio::spawn({
-            let map = map.clone();
-            async move {
-                let stream = stream.ready_chunks(UPDATE_CHUNK_SIZE);
-                futures::pin_mut!(stream);
+async fn metrics(
+    live: &LiveMetrics,
+    exposition: &Exposition,
+    tls_stats: Option<&tls::Stats>,
+    query: &str,
+    format: Format,
+) -> Response {
+    let filter = match SeriesFilter::from_query(query) {
+        Ok(filter) => filter,
+        Err(e) => return bad_request(e),
+    };
+
+    let map = snapshot(live, exposition).await;
+    let mut selection = select(&map, exposition, &filter);
+
+    if let Some(tls_stats) = tls_stats {
+        let name = "dprom_tls_rejected_clients_total";
+        if filter.matches(name) {
+            selection.counter(name, tls_stats.rejected_clients.load(Ordering::Relaxed));
+        }
+    }
 
-                while let Some(chunk) = stream.next().await {
-                    receive_chunk(&map, chunk)
-                }
+    reply(&selection, format)
+}
 
-                // should we restart here?
-                slog::warn!(log, "metric stream stopped! metrics no longer live");
-            }
-        });
+/// like /metrics, but as with Prometheus federation at least one `match[]`
+/// selector is required
+async fn federate(live: &LiveMetrics, exposition: &Exposition, query: &str, format: Format) -> Response {
+    let filter = match SeriesFilter::from_query(query) {
+        Ok(filter) => filter,
+        Err(e) => return bad_request(e),
+    };
 
-        LiveMetrics { map }
+    if filter.selectors.is_empty() {
+        return bad_request("at least one match[] parameter is required");
     }
 
-    pub fn read(&self) -> std::sync::RwLockReadGuard<MetricMap> {
-        self.map.read().unwrap()
-    }
+    let map = snapshot(live, exposition).await;
+    reply(&select(&map, exposition, &filter), format)
 }
 
-fn receive_chunk(map: &RwLock<MetricMap>, chunk: Vec<Record>) {
-    let mut map = map.write().unwrap();
+fn select(map: &MetricMap, exposition: &Exposition, filter: &SeriesFilter) -> Selection {
+    Selection::new(map, filter, exposition.timestamps, exposition.staleness.as_ref())
+}
 
-    for (name, value) in chunk {
-        match value {
-            Some(value) => { map.insert(name, value); }
-            None => { map.remove(&name); }
-        }
-    }
+fn reply(selection: &Selection, format: Format) -> Response {
+    warp::reply::with_header(selection.render(format), "content-type", format.content_type()).into_response()
 }
 
+pub fn bad_request(msg: impl Display) -> Response {
+    warp::reply::with_status(format!("{}\n", msg), StatusCode::BAD_REQUEST).into_response()
+}
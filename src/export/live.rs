@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use futures::stream::{Stream, StreamExt};
+use tokio::sync::broadcast;
+
+use crate::export::metric::{MetricName, MetricValue, Record, Source};
+
+const UPDATE_CHUNK_SIZE: usize = 64; // chosen arbritrarily
+
+/// updates buffered per subscriber before it starts missing them
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+#[derive(Clone)]
+pub struct LiveMetrics {
+    map: Arc<RwLock<MetricMap>>,
+    updates: broadcast::Sender<Update>,
+}
+
+// btree to keep it nicely sorted for output :)
+pub type MetricMap = BTreeMap<MetricName, Entry>;
+
+#[derive(Clone)]
+pub struct Entry {
+    pub value: MetricValue,
+    /// publisher supplied sample time, unix milliseconds
+    pub timestamp: Option<i64>,
+    /// when the series was created or last reset
+    pub created: SystemTime,
+    pub source: Arc<Source>,
+    /// when the most recent value was received
+    pub updated: SystemTime,
+}
+
+/// A change to the live metric map, `None` meaning the metric was removed
+pub type Update = (MetricName, Option<Entry>);
+
+impl LiveMetrics {
+    pub fn new(log: slog::Logger, stream: impl Stream<Item = Record> + Send + 'static) -> Self {
+        let map = Arc::new(RwLock::new(MetricMap::default()));
+        let (updates, _) = broadcast::channel(SUBSCRIBER_BUFFER);
+
+        tokio::spawn({
+            let map = map.clone();
+            let updates = updates.clone();
+            async move {
+                let stream = stream.ready_chunks(UPDATE_CHUNK_SIZE);
+                futures::pin_mut!(stream);
+
+                while let Some(chunk) = stream.next().await {
+                    receive_chunk(&map, &updates, chunk)
+                }
+
+                // should we restart here?
+                slog::warn!(log, "metric stream stopped! metrics no longer live");
+            }
+        });
+
+        LiveMetrics { map, updates }
+    }
+
+    /// Subscribes to every subsequent change to the metric map. Subscribers
+    /// never block the update path: a subscriber that falls too far behind
+    /// loses the oldest updates and sees a `Lagged` error instead.
+    pub fn subscribe(&self) -> broadcast::Receiver<Update> {
+        self.updates.subscribe()
+    }
+
+    pub fn read(&self) -> std::sync::RwLockReadGuard<'_, MetricMap> {
+        self.map.read().unwrap()
+    }
+}
+
+fn receive_chunk(map: &RwLock<MetricMap>, updates: &broadcast::Sender<Update>, chunk: Vec<Record>) {
+    let mut map = map.write().unwrap();
+    let now = SystemTime::now();
+
+    for (name, sample) in chunk {
+        let entry = sample.map(|sample| Entry {
+            value: sample.value,
+            timestamp: sample.timestamp,
+            created: sample.created,
+            source: sample.source,
+            updated: now,
+        });
+
+        match &entry {
+            Some(entry) => { map.insert(name.clone(), entry.clone()); }
+            None => { map.remove(&name); }
+        }
+
+        // only fails if there are no subscribers, which is fine:
+        let _ = updates.send((name, entry));
+    }
+}
@@ -1,18 +1,40 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::num::NonZeroU64;
 use std::sync::{Arc, Mutex};
 use std::fmt::{self, Display};
+use std::time::SystemTime;
 
 use derive_more::Display;
 use futures::stream::{self, Stream, StreamExt};
+use serde_derive::Serialize;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
 
 const MPSC_BUFFER: usize = 50;
 
-pub type Record = (MetricName, Option<MetricValue>);
+pub type Record = (MetricName, Option<Sample>);
+
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub value: MetricValue,
+    /// publisher supplied sample time, unix milliseconds
+    pub timestamp: Option<i64>,
+    /// when the series was created or last reset
+    pub created: SystemTime,
+    pub source: Arc<Source>,
+}
+
+/// The D-Bus object a metric is being read from
+#[derive(Debug, Serialize)]
+pub struct Source {
+    /// which dbus connection: "session" or "system"
+    pub connection: &'static str,
+    pub bus: String,
+    pub path: String,
+}
 
 pub struct Export {
+    log: slog::Logger,
     shared: Mutex<ExportShared>,
     gone_tx: mpsc::UnboundedSender<MetricName>,
     record_tx: mpsc::Sender<Record>,
@@ -21,22 +43,136 @@ pub struct Export {
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Display)]
 pub struct MetricName(Arc<String>);
 
+impl MetricName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// the name of the series saying whether this metric's publisher could
+    /// sample it, 1 if so and 0 if not
+    pub fn up(&self) -> MetricName {
+        MetricName::from(format!("{}_up", self.0))
+    }
+}
+
 impl<T> From<T> for MetricName where T: Into<String> {
     fn from(x: T) -> Self {
         MetricName(Arc::new(x.into()))
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
 pub enum MetricValue {
     Gauge(f64),
+    Counter {
+        value: u64,
+        exemplar: Option<Exemplar>,
+    },
+    Histogram {
+        /// upper bound and cumulative count, +Inf excluded
+        buckets: Vec<(f64, u64)>,
+        sum: f64,
+        count: u64,
+        exemplars: Vec<Exemplar>,
+    },
+    NativeHistogram(NativeHistogram),
+    /// textual information carried in labels, exposed as a series with value 1
+    Info(BTreeMap<String, String>),
+    /// one active state out of a fixed set, exposed as a series per state
+    StateSet { states: Vec<String>, state: String },
 }
 
 impl Display for MetricValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             MetricValue::Gauge(val) => write!(f, "{}", val),
+            MetricValue::Counter { value, .. } => write!(f, "{}", value),
+            MetricValue::Histogram { sum, count, .. } => write!(f, "{} / {}", sum, count),
+            MetricValue::NativeHistogram(histogram) => write!(f, "{} / {}", histogram.sum, histogram.count),
+            MetricValue::Info(labels) => write!(f, "{:?}", labels),
+            MetricValue::StateSet { state, .. } => write!(f, "{}", state),
+        }
+    }
+}
+
+impl MetricValue {
+    /// the monotonic count of counters and histograms, which only goes
+    /// down when the series is reset
+    fn count(&self) -> Option<u64> {
+        match self {
+            MetricValue::Counter { value, .. } => Some(*value),
+            MetricValue::Histogram { count, .. } => Some(*count),
+            MetricValue::NativeHistogram(histogram) => Some(histogram.count),
+            MetricValue::Gauge(_) | MetricValue::Info(_) | MetricValue::StateSet { .. } => None,
+        }
+    }
+}
+
+/// Prometheus native histogram with sparse exponential buckets, only
+/// exposed in full through the protobuf exposition format
+#[derive(Clone, Debug, Serialize)]
+pub struct NativeHistogram {
+    pub schema: i32,
+    pub zero_threshold: f64,
+    pub zero_count: u64,
+    pub count: u64,
+    pub sum: f64,
+    /// offset and length of each run of populated buckets
+    pub positive_spans: Vec<(i32, u32)>,
+    /// bucket counts, each as the difference from the previous bucket
+    pub positive_deltas: Vec<i64>,
+    pub negative_spans: Vec<(i32, u32)>,
+    pub negative_deltas: Vec<i64>,
+}
+
+impl NativeHistogram {
+    /// checks the histogram can be ingested by Prometheus
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(-4..=8).contains(&self.schema) {
+            anyhow::bail!("schema {} out of range -4 to 8", self.schema);
+        }
+
+        let spans_len = |spans: &[(i32, u32)]| spans.iter().map(|(_, length)| *length as usize).sum::<usize>();
+
+        if spans_len(&self.positive_spans) != self.positive_deltas.len() {
+            anyhow::bail!("positive spans cover {} buckets, but {} deltas given",
+                spans_len(&self.positive_spans), self.positive_deltas.len());
+        }
+
+        if spans_len(&self.negative_spans) != self.negative_deltas.len() {
+            anyhow::bail!("negative spans cover {} buckets, but {} deltas given",
+                spans_len(&self.negative_spans), self.negative_deltas.len());
+        }
+
+        Ok(())
+    }
+}
+
+/// Example of an observation, such as one carrying a trace id, attached to a
+/// counter or histogram bucket in the OpenMetrics exposition
+#[derive(Clone, Debug, Serialize)]
+pub struct Exemplar {
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+    /// unix milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+}
+
+impl Exemplar {
+    /// converts from the `(a{ss}dx)` D-Bus representation, where empty
+    /// labels mean no exemplar and a zero timestamp means unknown
+    pub fn from_dbus((labels, value, timestamp): (HashMap<String, String>, f64, i64)) -> Option<Self> {
+        if labels.is_empty() {
+            return None;
         }
+
+        Some(Exemplar {
+            labels: labels.into_iter().collect(),
+            value,
+            timestamp: (timestamp != 0).then_some(timestamp),
+        })
     }
 }
 
@@ -74,14 +210,23 @@ pub struct MetricHandle<'a> {
     export: &'a Export,
     uniq: NonZeroU64,
     name: MetricName,
+    source: Arc<Source>,
+    series: Mutex<Series>,
+}
+
+/// state carried between samples of a metric for reset detection
+struct Series {
+    created: SystemTime,
+    count: Option<u64>,
 }
 
 impl Export {
-    pub fn new() -> (Self, impl Stream<Item = Record>) {
+    pub fn new(log: slog::Logger) -> (Self, impl Stream<Item = Record>) {
         let (record_tx, record_rx) = mpsc::channel(MPSC_BUFFER);
         let (gone_tx, gone_rx) = mpsc::unbounded_channel();
 
         let export = Export {
+            log,
             shared: Default::default(),
             record_tx,
             gone_tx,
@@ -94,7 +239,7 @@ impl Export {
         (export, stream::select(gone_stream, record_stream))
     }
 
-    pub fn metric(&self, name: impl Into<MetricName>) -> MetricHandle<'_> {
+    pub fn metric(&self, name: impl Into<MetricName>, source: Source) -> MetricHandle<'_> {
         let mut shared = self.shared.lock().unwrap();
         let name = name.into();
         let uniq = next(&mut shared.serial);
@@ -109,22 +254,30 @@ impl Export {
             export: self,
             uniq,
             name,
+            source: Arc::new(source),
+            series: Mutex::new(Series {
+                created: SystemTime::now(),
+                count: None,
+            }),
         }
     }
 }
 
 impl<'a> MetricHandle<'a> {
-    pub async fn gauge(&self, value: f64) {
-        self.measure(MetricValue::Gauge(value)).await;
+    pub async fn gauge(&self, value: f64, timestamp: Option<i64>) {
+        self.measure(MetricValue::Gauge(value), timestamp).await;
     }
 
-    pub async fn measure(&self, value: MetricValue) {
+    pub async fn measure(&self, value: MetricValue, timestamp: Option<i64>) {
+        let created = self.observe(&value);
+
         let record = {
             let shared = self.export.shared.lock().unwrap();
 
             if shared.get_uniq(&self.name) == Some(self.uniq) {
                 let name = self.name.clone();
-                Some((name, Some(value)))
+                let source = self.source.clone();
+                Some((name, Some(Sample { value, timestamp, created, source })))
             } else {
                 None
             }
@@ -136,6 +289,26 @@ impl<'a> MetricHandle<'a> {
     }
 }
 
+impl<'a> MetricHandle<'a> {
+    /// returns the creation time of the series, moved forward when a
+    /// counter or histogram count goes down, meaning the publisher reset it
+    fn observe(&self, value: &MetricValue) -> SystemTime {
+        let mut series = self.series.lock().unwrap();
+        let Some(count) = value.count() else { return series.created };
+
+        if let Some(previous) = series.count.filter(|previous| count < *previous) {
+            slog::warn!(self.export.log, "counter reset";
+                "metric" => self.name.as_str(), "previous" => previous, "value" => count,
+                "dbus" => self.source.connection, "bus" => &self.source.bus, "path" => &self.source.path);
+
+            series.created = SystemTime::now();
+        }
+
+        series.count = Some(count);
+        series.created
+    }
+}
+
 impl<'a> Drop for MetricHandle<'a> {
     fn drop(&mut self) {
         let mut shared = self.export.shared.lock().unwrap();
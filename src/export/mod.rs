@@ -1,8 +1,20 @@
+pub mod api;
+pub mod collect;
 pub mod config;
 pub mod context;
 pub mod dbus;
+pub mod format;
 pub mod http;
+pub mod live;
 pub mod metric;
+pub mod page;
+pub mod proto;
+pub mod selector;
+pub mod status;
+pub mod text;
+pub mod tls;
+
+use std::sync::Arc;
 
 use futures::future;
 use structopt::StructOpt;
@@ -17,11 +29,27 @@ pub async fn run(log: slog::Logger, opt: Opt) -> anyhow::Result<()> {
     let config = config::open(&opt.config).await
         .map_err(|e| e.context("opening config"))?;
 
-    let (export, metric_stream) = metric::Export::new();
+    let shutdown = crate::shutdown::listen(log.clone())?;
+
+    let (export, metric_stream) = metric::Export::new(log.clone());
+    let status = Arc::new(status::Status::new());
 
-    let dbus = tokio::spawn(dbus::run(log.clone(), export, config.dbus));
-    let http = tokio::spawn(http::run(log.clone(), metric_stream, config.http));
+    let collector = match config.dbus.mode {
+        config::Mode::Push => None,
+        config::Mode::Pull => {
+            let timeout = config.dbus.collect_timeout_secs;
+            Some(Arc::new(collect::Collector::new(log.clone(), timeout)))
+        }
+    };
 
+    let dbus = tokio::spawn(dbus::run(log.clone(), export, status.clone(), collector.clone(), config.dbus));
+    let http = tokio::spawn(http::run(log.clone(), metric_stream, status, collector, shutdown, config.http));
+
+    // http only returns without error once in-flight requests have finished
+    // after a shutdown signal, so that they still see live values. dbus
+    // owns no objects, its connections are closed as the runtime drops it
     future::select(dbus, http).await.factor_first().0??;
+
+    slog::info!(log, "shut down");
     Ok(())
 }
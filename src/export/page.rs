@@ -0,0 +1,78 @@
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::export::live::LiveMetrics;
+use crate::export::status::Status;
+
+/// Human readable HTML overview of the exporter, served at the root route
+pub fn status(live: &LiveMetrics, status: &Status) -> String {
+    let version = env!("CARGO_PKG_VERSION");
+    let mut html = String::new();
+
+    let _ = writeln!(html, "<!DOCTYPE html>");
+    let _ = writeln!(html, "<html><head><title>dprom-export</title></head><body><pre>");
+    let _ = writeln!(html, "dprom-export {version}");
+    let _ = writeln!(html);
+    let _ = writeln!(html, "<a href=\"/metrics\">/metrics</a>  <a href=\"/api/v1/metrics\">/api/v1/metrics</a>  <a href=\"/api/v1/buses\">/api/v1/buses</a>");
+    let _ = writeln!(html);
+    let _ = writeln!(html, "uptime:  {}", format_duration(status.uptime()));
+    let _ = writeln!(html, "ready:   {}", status.is_ready());
+    let _ = writeln!(html, "metrics: {}", live.read().len());
+
+    let _ = writeln!(html);
+    let _ = writeln!(html, "<b>connections</b>");
+    for (connection, ready) in status.connections() {
+        let state = if ready { "ready" } else { "scanning" };
+        let _ = writeln!(html, "  {connection:<8} {state}");
+    }
+
+    let buses = status.buses();
+    let peers = buses.iter().filter(|bus| bus.dprom).collect::<Vec<_>>();
+
+    let _ = writeln!(html);
+    let _ = writeln!(html, "<b>peers</b> ({} of {} buses expose D-Prom)", peers.len(), buses.len());
+    for peer in peers {
+        let _ = writeln!(html, "  {:<8} {:<12} {} metrics",
+            peer.connection, escape(&peer.bus), peer.metrics.len());
+
+        for path in &peer.metrics {
+            let _ = writeln!(html, "      {}", escape(path));
+        }
+    }
+
+    let _ = writeln!(html);
+    let _ = writeln!(html, "<b>recent errors</b>");
+    for error in status.errors() {
+        let _ = writeln!(html, "  [{}] {}", unix_secs(error.time), escape(&error.message));
+    }
+
+    let _ = writeln!(html, "</pre></body></html>");
+    html
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}d {:02}h {:02}m {:02}s", secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60)
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
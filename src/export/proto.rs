@@ -0,0 +1,312 @@
+use prost::Message;
+
+use crate::export::format::{bucket_exemplar, label_name, Selected, Selection};
+use crate::export::metric::{self, MetricValue};
+
+/// renders in the delimited protobuf exposition format, a sequence of
+/// length prefixed `io.prometheus.client.MetricFamily` messages
+pub fn render(selection: &Selection) -> Vec<u8> {
+    let mut families = selection.metrics.iter()
+        .map(family)
+        .collect::<Vec<_>>();
+
+    if !selection.stale.is_empty() {
+        families.push(MetricFamily {
+            name: Some("dprom_metric_age_seconds".to_owned()),
+            r#type: Some(MetricType::Gauge as i32),
+            metric: selection.stale.iter()
+                .map(|(name, age)| Metric {
+                    label: vec![label_pair("metric", name)],
+                    gauge: Some(Gauge { value: Some(age.as_secs_f64()) }),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        });
+    }
+
+    let mut buf = Vec::new();
+
+    for family in families {
+        // only fails when out of capacity, which a Vec never is:
+        let _ = family.encode_length_delimited(&mut buf);
+    }
+
+    buf
+}
+
+fn family(selected: &Selected) -> MetricFamily {
+    let metric = |label, value| Metric {
+        label,
+        timestamp_ms: selected.timestamp,
+        ..value
+    };
+
+    let (type_, metrics) = match &selected.value {
+        MetricValue::Gauge(value) => {
+            (MetricType::Gauge, vec![metric(vec![], gauge(*value))])
+        }
+        MetricValue::Counter { value, exemplar } => {
+            let counter = Metric {
+                counter: Some(Counter {
+                    value: Some(*value as f64),
+                    exemplar: exemplar.as_ref().map(Exemplar::from),
+                    created_timestamp: selected.created.map(Timestamp::from_millis),
+                }),
+                ..Default::default()
+            };
+
+            (MetricType::Counter, vec![metric(vec![], counter)])
+        }
+        MetricValue::Histogram { buckets, sum, count, exemplars } => {
+            // the +Inf bucket is implied by the sample count:
+            let bucket = buckets.iter()
+                .map(|(le, count)| Bucket {
+                    cumulative_count: Some(*count),
+                    upper_bound: Some(*le),
+                    exemplar: bucket_exemplar(buckets, exemplars, *le).map(Exemplar::from),
+                })
+                .collect();
+
+            let histogram = Metric {
+                histogram: Some(Histogram {
+                    sample_count: Some(*count),
+                    sample_sum: Some(*sum),
+                    bucket,
+                    created_timestamp: selected.created.map(Timestamp::from_millis),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+
+            (MetricType::Histogram, vec![metric(vec![], histogram)])
+        }
+        MetricValue::NativeHistogram(native) => {
+            let spans = |spans: &[(i32, u32)]| spans.iter()
+                .map(|(offset, length)| BucketSpan { offset: Some(*offset), length: Some(*length) })
+                .collect::<Vec<_>>();
+
+            let mut positive_span = spans(&native.positive_spans);
+
+            // Prometheus tells native histograms from classic ones by their
+            // spans and zero bucket, so one with neither yet gets an empty
+            // span as client_golang does:
+            if native.positive_spans.is_empty() && native.negative_spans.is_empty()
+                && native.zero_threshold == 0.0 && native.zero_count == 0
+            {
+                positive_span.push(BucketSpan { offset: Some(0), length: Some(0) });
+            }
+
+            let histogram = Metric {
+                histogram: Some(Histogram {
+                    sample_count: Some(native.count),
+                    sample_sum: Some(native.sum),
+                    created_timestamp: selected.created.map(Timestamp::from_millis),
+                    schema: Some(native.schema),
+                    zero_threshold: Some(native.zero_threshold),
+                    zero_count: Some(native.zero_count),
+                    negative_span: spans(&native.negative_spans),
+                    negative_delta: native.negative_deltas.clone(),
+                    positive_span,
+                    positive_delta: native.positive_deltas.clone(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+
+            (MetricType::Histogram, vec![metric(vec![], histogram)])
+        }
+        MetricValue::Info(labels) => {
+            let labels = labels.iter()
+                .map(|(name, value)| label_pair(&label_name(name), value))
+                .collect();
+
+            (MetricType::Gauge, vec![metric(labels, gauge(1.0))])
+        }
+        MetricValue::StateSet { states, state } => {
+            // as in the text formats, one series per state with a label
+            // named after the metric:
+            let label = label_name(&selected.name);
+            let missing = (!states.contains(state)).then_some(state);
+
+            let metrics = states.iter()
+                .chain(missing)
+                .map(|current| {
+                    let value = if current == state { 1.0 } else { 0.0 };
+                    metric(vec![label_pair(&label, current)], gauge(value))
+                })
+                .collect();
+
+            (MetricType::Gauge, metrics)
+        }
+    };
+
+    MetricFamily {
+        name: Some(selected.name.clone()),
+        r#type: Some(type_ as i32),
+        metric: metrics,
+        ..Default::default()
+    }
+}
+
+fn gauge(value: f64) -> Metric {
+    Metric {
+        gauge: Some(Gauge { value: Some(value) }),
+        ..Default::default()
+    }
+}
+
+fn label_pair(name: &str, value: &str) -> LabelPair {
+    LabelPair {
+        name: Some(name.to_owned()),
+        value: Some(value.to_owned()),
+    }
+}
+
+impl From<&metric::Exemplar> for Exemplar {
+    fn from(exemplar: &metric::Exemplar) -> Self {
+        Exemplar {
+            label: exemplar.labels.iter()
+                .map(|(name, value)| label_pair(&label_name(name), value))
+                .collect(),
+            value: Some(exemplar.value),
+            timestamp: exemplar.timestamp.map(Timestamp::from_millis),
+        }
+    }
+}
+
+impl Timestamp {
+    fn from_millis(millis: i64) -> Self {
+        Timestamp {
+            seconds: millis.div_euclid(1000),
+            nanos: (millis.rem_euclid(1000) * 1_000_000) as i32,
+        }
+    }
+}
+
+// messages below are the subset of Prometheus' metrics.proto in use here,
+// written out by hand to avoid needing protoc at build time
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MetricFamily {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub help: Option<String>,
+    #[prost(enumeration = "MetricType", optional, tag = "3")]
+    pub r#type: Option<i32>,
+    #[prost(message, repeated, tag = "4")]
+    pub metric: Vec<Metric>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum MetricType {
+    Counter = 0,
+    Gauge = 1,
+    Summary = 2,
+    Untyped = 3,
+    Histogram = 4,
+    GaugeHistogram = 5,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Metric {
+    #[prost(message, repeated, tag = "1")]
+    pub label: Vec<LabelPair>,
+    #[prost(message, optional, tag = "2")]
+    pub gauge: Option<Gauge>,
+    #[prost(message, optional, tag = "3")]
+    pub counter: Option<Counter>,
+    #[prost(message, optional, tag = "7")]
+    pub histogram: Option<Histogram>,
+    #[prost(int64, optional, tag = "6")]
+    pub timestamp_ms: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LabelPair {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub value: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Gauge {
+    #[prost(double, optional, tag = "1")]
+    pub value: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Counter {
+    #[prost(double, optional, tag = "1")]
+    pub value: Option<f64>,
+    #[prost(message, optional, tag = "2")]
+    pub exemplar: Option<Exemplar>,
+    #[prost(message, optional, tag = "3")]
+    pub created_timestamp: Option<Timestamp>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Histogram {
+    #[prost(uint64, optional, tag = "1")]
+    pub sample_count: Option<u64>,
+    #[prost(double, optional, tag = "2")]
+    pub sample_sum: Option<f64>,
+    #[prost(message, repeated, tag = "3")]
+    pub bucket: Vec<Bucket>,
+    #[prost(sint32, optional, tag = "5")]
+    pub schema: Option<i32>,
+    #[prost(double, optional, tag = "6")]
+    pub zero_threshold: Option<f64>,
+    #[prost(uint64, optional, tag = "7")]
+    pub zero_count: Option<u64>,
+    #[prost(message, repeated, tag = "9")]
+    pub negative_span: Vec<BucketSpan>,
+    #[prost(sint64, repeated, tag = "10")]
+    pub negative_delta: Vec<i64>,
+    #[prost(message, repeated, tag = "12")]
+    pub positive_span: Vec<BucketSpan>,
+    #[prost(sint64, repeated, tag = "13")]
+    pub positive_delta: Vec<i64>,
+    #[prost(message, optional, tag = "15")]
+    pub created_timestamp: Option<Timestamp>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Bucket {
+    #[prost(uint64, optional, tag = "1")]
+    pub cumulative_count: Option<u64>,
+    #[prost(double, optional, tag = "2")]
+    pub upper_bound: Option<f64>,
+    #[prost(message, optional, tag = "3")]
+    pub exemplar: Option<Exemplar>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BucketSpan {
+    #[prost(sint32, optional, tag = "1")]
+    pub offset: Option<i32>,
+    #[prost(uint32, optional, tag = "2")]
+    pub length: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Exemplar {
+    #[prost(message, repeated, tag = "1")]
+    pub label: Vec<LabelPair>,
+    #[prost(double, optional, tag = "2")]
+    pub value: Option<f64>,
+    #[prost(message, optional, tag = "3")]
+    pub timestamp: Option<Timestamp>,
+}
+
+/// `google.protobuf.Timestamp`
+#[derive(Clone, PartialEq, Message)]
+pub struct Timestamp {
+    #[prost(int64, tag = "1")]
+    pub seconds: i64,
+    #[prost(int32, tag = "2")]
+    pub nanos: i32,
+}
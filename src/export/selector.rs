@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use regex::Regex;
+
+/// A Prometheus-style series selector, such as those accepted in `match[]`
+/// parameters: `name{label="value", other=~"regex"}`. Either the name or
+/// the braced matcher list may be omitted, but not both, and at least one
+/// matcher must not match the empty string.
+pub struct Selector {
+    matchers: Vec<Matcher>,
+}
+
+struct Matcher {
+    label: String,
+    op: Op,
+}
+
+enum Op {
+    Eq(String),
+    Ne(String),
+    Re(Regex),
+    NotRe(Regex),
+}
+
+#[derive(Debug)]
+pub struct ParseError(String);
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid selector: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Selector {
+    /// `label` looks up a label on the series being tested, returning `None`
+    /// when the series lacks it. As in Prometheus, missing labels compare
+    /// equal to the empty string.
+    pub fn matches<'a>(&self, name: &str, label: impl Fn(&str) -> Option<&'a str>) -> bool {
+        self.matchers.iter().all(|matcher| {
+            let value = match matcher.label.as_str() {
+                "__name__" => name,
+                other => label(other).unwrap_or(""),
+            };
+
+            matcher.op.matches(value)
+        })
+    }
+}
+
+impl Op {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Op::Eq(expected) => value == expected,
+            Op::Ne(expected) => value != expected,
+            Op::Re(re) => re.is_match(value),
+            Op::NotRe(re) => !re.is_match(value),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser { rest: s.trim() };
+        let mut matchers = Vec::new();
+
+        let name = parser.ident();
+        if !name.is_empty() {
+            matchers.push(Matcher { label: "__name__".to_owned(), op: Op::Eq(name.to_owned()) });
+        }
+
+        if parser.eat("{") {
+            while !parser.eat("}") {
+                matchers.push(parser.matcher()?);
+
+                if !parser.eat(",") && !parser.peek("}") {
+                    return Err(parser.error("expected ',' or '}'"));
+                }
+            }
+        }
+
+        if !parser.rest.is_empty() {
+            return Err(parser.error("unexpected trailing characters"));
+        }
+
+        if matchers.is_empty() {
+            return Err(ParseError("selector must contain at least one matcher".to_owned()));
+        }
+
+        // as in Prometheus, a selector matching every series is refused:
+        if matchers.iter().all(|matcher| matcher.op.matches("")) {
+            return Err(ParseError("selector must contain at least one matcher not matching the empty string".to_owned()));
+        }
+
+        Ok(Selector { matchers })
+    }
+}
+
+/// Series filter built from the `name[]` and `match[]` query parameters. A
+/// series is rendered if its name is listed in `name[]` (when given) and it
+/// matches any `match[]` selector (when given).
+pub struct SeriesFilter {
+    pub names: HashSet<String>,
+    pub selectors: Vec<Selector>,
+}
+
+impl SeriesFilter {
+    pub fn from_query(query: &str) -> anyhow::Result<Self> {
+        let params = serde_urlencoded::from_str::<Vec<(String, String)>>(query)?;
+
+        let mut filter = SeriesFilter {
+            names: HashSet::new(),
+            selectors: Vec::new(),
+        };
+
+        for (key, value) in params {
+            match key.as_str() {
+                "name[]" => { filter.names.insert(value); }
+                "match[]" => { filter.selectors.push(value.parse()?); }
+                _ => {}
+            }
+        }
+
+        Ok(filter)
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.matches_series(name, |_| None)
+    }
+
+    /// as `matches`, for series carrying labels
+    pub fn matches_series<'a>(&self, name: &str, label: impl Fn(&str) -> Option<&'a str>) -> bool {
+        let name_ok = self.names.is_empty() || self.names.contains(name);
+
+        let selector_ok = self.selectors.is_empty() ||
+            self.selectors.iter().any(|selector| selector.matches(name, &label));
+
+        name_ok && selector_ok
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> ParseError {
+        ParseError(format!("{} at {:?}", msg, self.rest))
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn peek(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        self.rest.starts_with(token)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.peek(token) {
+            self.rest = &self.rest[token.len()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let end = self.rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == ':'))
+            .unwrap_or(self.rest.len());
+        let (ident, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        ident
+    }
+
+    fn matcher(&mut self) -> Result<Matcher, ParseError> {
+        let label = self.ident();
+        if label.is_empty() {
+            return Err(self.error("expected label name"));
+        }
+
+        // order matters, longer tokens first:
+        let op = ["=~", "!~", "!=", "="].into_iter()
+            .find(|op| self.eat(op))
+            .ok_or_else(|| self.error("expected one of =, !=, =~, !~"))?;
+
+        let value = self.string()?;
+
+        let op = match op {
+            "=" => Op::Eq(value),
+            "!=" => Op::Ne(value),
+            "=~" => Op::Re(anchored_regex(&value)?),
+            "!~" => Op::NotRe(anchored_regex(&value)?),
+            _ => unreachable!(),
+        };
+
+        Ok(Matcher { label: label.to_owned(), op })
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.skip_whitespace();
+
+        let mut chars = self.rest.char_indices();
+
+        let quote = match chars.next() {
+            Some((_, quote @ ('"' | '\''))) => quote,
+            _ => return Err(self.error("expected quoted string")),
+        };
+
+        let mut value = String::new();
+
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, c)) => value.push(c),
+                    None => break,
+                },
+                c if c == quote => {
+                    self.rest = &self.rest[idx + c.len_utf8()..];
+                    return Ok(value);
+                }
+                c => value.push(c),
+            }
+        }
+
+        Err(self.error("unterminated string"))
+    }
+}
+
+/// Prometheus regex matchers must match the entire label value
+fn anchored_regex(re: &str) -> Result<Regex, ParseError> {
+    Regex::new(&format!("^(?:{})$", re))
+        .map_err(|e| ParseError(e.to_string()))
+}
@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde_derive::Serialize;
+
+/// how many recent errors are kept for the status page
+const RECENT_ERRORS: usize = 50;
+
+/// Shared view of what the exporter is currently doing, for the benefit of
+/// the HTTP API. Updated by the dbus side as buses come and go.
+pub struct Status {
+    started: Instant,
+    connections: Mutex<BTreeMap<&'static str, bool>>,
+    buses: Mutex<BTreeMap<BusKey, BusStatus>>,
+    errors: Mutex<VecDeque<ErrorRecord>>,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct BusKey {
+    connection: &'static str,
+    bus: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct BusStatus {
+    pub connection: &'static str,
+    pub bus: String,
+    /// whether this bus exposes the D-Prom interface. buses are probed as
+    /// soon as they appear, so this is false for most of them
+    pub dprom: bool,
+    pub metrics: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct ErrorRecord {
+    pub time: SystemTime,
+    pub message: String,
+}
+
+/// Registration of a bus task in `Status`, removed again on drop.
+pub struct BusHandle<'a> {
+    status: &'a Status,
+    key: BusKey,
+}
+
+impl Status {
+    pub fn new() -> Self {
+        Status {
+            started: Instant::now(),
+            connections: Default::default(),
+            buses: Default::default(),
+            errors: Default::default(),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// registers a dbus connection, not ready until `set_ready` is called
+    pub fn connection(&self, connection: &'static str) {
+        self.connections.lock().unwrap().insert(connection, false);
+    }
+
+    /// marks a connection as having completed its initial scan of the bus
+    pub fn set_ready(&self, connection: &'static str) {
+        self.connections.lock().unwrap().insert(connection, true);
+    }
+
+    /// ready once at least one connection has completed its initial scan
+    pub fn is_ready(&self) -> bool {
+        self.connections.lock().unwrap().values().any(|ready| *ready)
+    }
+
+    /// each registered connection and whether it is ready
+    pub fn connections(&self) -> Vec<(&'static str, bool)> {
+        self.connections.lock().unwrap()
+            .iter()
+            .map(|(conn, ready)| (*conn, *ready))
+            .collect()
+    }
+
+    pub fn bus(&self, connection: &'static str, bus: String) -> BusHandle<'_> {
+        let key = BusKey { connection, bus };
+
+        self.buses.lock().unwrap().insert(key.clone(), BusStatus {
+            connection,
+            bus: key.bus.clone(),
+            dprom: false,
+            metrics: Vec::new(),
+        });
+
+        BusHandle {
+            status: self,
+            key,
+        }
+    }
+
+    pub fn buses(&self) -> Vec<BusStatus> {
+        self.buses.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn error(&self, message: String) {
+        let mut errors = self.errors.lock().unwrap();
+
+        if errors.len() == RECENT_ERRORS {
+            errors.pop_front();
+        }
+
+        errors.push_back(ErrorRecord {
+            time: SystemTime::now(),
+            message,
+        });
+    }
+
+    /// recent errors, most recent first
+    pub fn errors(&self) -> Vec<ErrorRecord> {
+        self.errors.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Status::new()
+    }
+}
+
+impl<'a> BusHandle<'a> {
+    /// marks the bus as a D-Prom bus and records its current metric paths
+    pub fn set_metrics(&self, metrics: Vec<String>) {
+        let mut buses = self.status.buses.lock().unwrap();
+
+        if let Some(bus) = buses.get_mut(&self.key) {
+            bus.dprom = true;
+            bus.metrics = metrics;
+        }
+    }
+}
+
+impl<'a> Drop for BusHandle<'a> {
+    fn drop(&mut self) {
+        self.status.buses.lock().unwrap().remove(&self.key);
+    }
+}
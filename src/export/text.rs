@@ -0,0 +1,200 @@
+use std::fmt::Write;
+
+use crate::export::format::{bucket_exemplar, label_name, Selection};
+use crate::export::metric::{Exemplar, MetricValue};
+
+/// renders in the Prometheus text format, or OpenMetrics which adds
+/// exemplars and native info and stateset types
+pub fn render(selection: &Selection, open_metrics: bool) -> String {
+    let mut writer = Writer {
+        open_metrics,
+        output: String::new(),
+    };
+
+    for selected in &selection.metrics {
+        writer.value(&selected.name, &selected.value, selected.timestamp);
+
+        // OpenMetrics has counters and histograms carry their creation time
+        // in a separate series:
+        if let (true, Some(created)) = (open_metrics, selected.created) {
+            writer.created(&selected.name, &selected.value, created, selected.timestamp);
+        }
+    }
+
+    if !selection.stale.is_empty() {
+        writer.family("dprom_metric_age_seconds", "gauge");
+
+        for (name, age) in &selection.stale {
+            let labels = format!("metric=\"{}\"", escape_label_value(name));
+            writer.sample("dprom_metric_age_seconds", &labels, &age.as_secs_f64().to_string(), None, None);
+        }
+    }
+
+    if open_metrics {
+        writer.output.push_str("# EOF\n");
+    }
+
+    writer.output
+}
+
+struct Writer {
+    open_metrics: bool,
+    output: String,
+}
+
+impl Writer {
+    fn value(&mut self, name: &str, value: &MetricValue, timestamp: Option<i64>) {
+        let om = self.open_metrics;
+
+        match value {
+            MetricValue::Gauge(value) => {
+                self.family(name, "gauge");
+                self.sample(name, "", &format_float(*value), timestamp, None);
+            }
+            MetricValue::Counter { value, exemplar } => {
+                // OpenMetrics counter families are named without the _total
+                // suffix their samples carry
+                let family = name.strip_suffix("_total").unwrap_or(name);
+                let (family, sample) = match om {
+                    true => (family, format!("{}_total", family)),
+                    false => (name, name.to_owned()),
+                };
+
+                self.family(family, "counter");
+                self.sample(&sample, "", &value.to_string(), timestamp, exemplar.as_ref());
+            }
+            MetricValue::Histogram { buckets, sum, count, exemplars } => {
+                self.family(name, "histogram");
+
+                let bucket = format!("{}_bucket", name);
+                let bounds = buckets.iter().map(|(le, _)| *le).chain([f64::INFINITY]);
+                let counts = buckets.iter().map(|(_, count)| *count).chain([*count]);
+
+                for (le, count) in bounds.zip(counts) {
+                    let exemplar = bucket_exemplar(buckets, exemplars, le);
+                    let labels = format!("le=\"{}\"", format_float(le));
+                    self.sample(&bucket, &labels, &count.to_string(), timestamp, exemplar);
+                }
+
+                self.sample(&format!("{}_sum", name), "", &format_float(*sum), timestamp, None);
+                self.sample(&format!("{}_count", name), "", &count.to_string(), timestamp, None);
+            }
+            MetricValue::NativeHistogram(histogram) => {
+                // sparse buckets can't be expressed in text, leaving only
+                // the +Inf bucket of a classic histogram:
+                self.family(name, "histogram");
+
+                let count = histogram.count.to_string();
+                self.sample(&format!("{}_bucket", name), "le=\"+Inf\"", &count, timestamp, None);
+                self.sample(&format!("{}_sum", name), "", &format_float(histogram.sum), timestamp, None);
+                self.sample(&format!("{}_count", name), "", &count, timestamp, None);
+            }
+            MetricValue::Info(labels) => {
+                // info and stateset have no type of their own in the
+                // Prometheus text format, both are conventionally exposed
+                // as gauges
+                match om {
+                    true => self.family(name.strip_suffix("_info").unwrap_or(name), "info"),
+                    false => self.family(name, "gauge"),
+                }
+
+                self.sample(name, &format_labels(labels), "1", timestamp, None);
+            }
+            MetricValue::StateSet { states, state } => {
+                self.family(name, if om { "stateset" } else { "gauge" });
+
+                // the state label is named after the metric itself. an
+                // active state missing from the set is still exposed:
+                let label = label_name(name);
+                let missing = (!states.contains(state)).then_some(state);
+
+                for current in states.iter().chain(missing) {
+                    let labels = format!("{}=\"{}\"", label, escape_label_value(current));
+                    let value = if current == state { "1" } else { "0" };
+                    self.sample(name, &labels, value, timestamp, None);
+                }
+            }
+        }
+    }
+
+    fn created(&mut self, name: &str, value: &MetricValue, created: i64, timestamp: Option<i64>) {
+        let family = match value {
+            MetricValue::Counter { .. } => name.strip_suffix("_total").unwrap_or(name),
+            MetricValue::Histogram { .. } | MetricValue::NativeHistogram(_) => name,
+            MetricValue::Gauge(_) | MetricValue::Info(_) | MetricValue::StateSet { .. } => { return; }
+        };
+
+        let created = self.timestamp(created);
+        self.sample(&format!("{}_created", family), "", &created, timestamp, None);
+    }
+
+    fn family(&mut self, name: &str, type_: &str) {
+        let _ = writeln!(&mut self.output, "# TYPE {} {}", name, type_);
+    }
+
+    fn sample(
+        &mut self,
+        name: &str,
+        labels: &str,
+        value: &str,
+        timestamp: Option<i64>,
+        exemplar: Option<&Exemplar>,
+    ) {
+        let _ = write!(&mut self.output, "{}", name);
+
+        if !labels.is_empty() {
+            let _ = write!(&mut self.output, "{{{}}}", labels);
+        }
+
+        let _ = write!(&mut self.output, " {}", value);
+
+        if let Some(timestamp) = timestamp {
+            let timestamp = self.timestamp(timestamp);
+            let _ = write!(&mut self.output, " {}", timestamp);
+        }
+
+        // exemplars can only be expressed in OpenMetrics:
+        if let Some(exemplar) = exemplar.filter(|_| self.open_metrics) {
+            let _ = write!(&mut self.output, " # {{{}}} {}",
+                format_labels(&exemplar.labels), format_float(exemplar.value));
+
+            if let Some(timestamp) = exemplar.timestamp {
+                let timestamp = self.timestamp(timestamp);
+                let _ = write!(&mut self.output, " {}", timestamp);
+            }
+        }
+
+        let _ = writeln!(&mut self.output);
+    }
+
+    /// milliseconds in the Prometheus text format, seconds in OpenMetrics
+    fn timestamp(&self, millis: i64) -> String {
+        match self.open_metrics {
+            false => millis.to_string(),
+            true => format!("{}.{:03}", millis.div_euclid(1000), millis.rem_euclid(1000)),
+        }
+    }
+}
+
+fn format_labels<'a>(labels: impl IntoIterator<Item = (&'a String, &'a String)>) -> String {
+    labels.into_iter()
+        .map(|(label, value)| format!("{}=\"{}\"", label_name(label), escape_label_value(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// as `Display`, but spelling infinities the way both formats expect
+fn format_float(value: f64) -> String {
+    match value {
+        f64::INFINITY => "+Inf".to_owned(),
+        f64::NEG_INFINITY => "-Inf".to_owned(),
+        value => value.to_string(),
+    }
+}
+
+/// escapes a label value per the text exposition format
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,293 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use futures::stream::{Stream, StreamExt};
+use rustls::server::{AllowAnyAuthenticatedClient, NoClientAuth};
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::export::config;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const ACCEPT_BUFFER: usize = 16;
+/// pause after an accept error which isn't specific to one connection, such
+/// as running out of file descriptors, which would otherwise recur at once
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct Stats {
+    /// clients which completed a handshake but were refused by the subject
+    /// allowlist
+    pub rejected_clients: AtomicU64,
+}
+
+/// Loads the TLS configuration and keeps it up to date. The returned watch
+/// always holds the most recently successfully loaded server config, which is
+/// picked up by new connections as they are accepted. Reloads happen on SIGHUP
+/// and whenever the modification time of any of the cert, key or CA files
+/// changes.
+pub async fn watch(log: slog::Logger, tls: config::Tls) -> anyhow::Result<watch::Receiver<Arc<ServerConfig>>> {
+    let mut hangup = signal(SignalKind::hangup())
+        .context("installing SIGHUP handler")?;
+
+    let mut stamp = modified(&tls).await;
+    let server_config = load(&tls).await
+        .context("loading TLS config")?;
+
+    let (tx, rx) = watch::channel(server_config);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tls.reload_secs);
+
+        // first tick completes immediately
+        interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let new_stamp = modified(&tls).await;
+                    if new_stamp == stamp {
+                        continue;
+                    }
+                    stamp = new_stamp;
+                    slog::info!(log, "TLS files changed, reloading");
+                }
+                _ = hangup.recv() => {
+                    stamp = modified(&tls).await;
+                    slog::info!(log, "received SIGHUP, reloading TLS config");
+                }
+                _ = tx.closed() => {
+                    break;
+                }
+            }
+
+            match load(&tls).await {
+                Ok(server_config) => {
+                    let _ = tx.send(server_config);
+                    slog::info!(log, "reloaded TLS config");
+                }
+                Err(e) => {
+                    slog::error!(log, "error reloading TLS config, keeping previous: {:?}", e);
+                }
+            }
+        }
+    });
+
+    Ok(rx)
+}
+
+/// Accepts TCP connections on `listener` and performs TLS handshakes using
+/// whichever server config is current at the time the connection arrives.
+/// Handshakes run concurrently so that a slow client cannot hold up others.
+/// Clients whose certificate does not match any `allow` pattern are dropped
+/// after the handshake, unless `allow` is empty.
+pub fn incoming(
+    log: slog::Logger,
+    listener: TcpListener,
+    server_config: watch::Receiver<Arc<ServerConfig>>,
+    allow: Arc<Vec<glob::Pattern>>,
+    stats: Arc<Stats>,
+) -> impl Stream<Item = io::Result<TlsStream<TcpStream>>> {
+    let (tx, rx) = mpsc::channel(ACCEPT_BUFFER);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = tokio::select! {
+                result = listener.accept() => match result {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        slog::warn!(log, "error accepting connection: {:?}", e);
+
+                        if !is_connection_error(&e) {
+                            tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                        }
+
+                        continue;
+                    }
+                },
+                _ = tx.closed() => { break; }
+            };
+
+            let acceptor = TlsAcceptor::from(server_config.borrow().clone());
+            let log = log.new(slog::o!("peer" => peer.to_string()));
+            let tx = tx.clone();
+            let allow = allow.clone();
+            let stats = stats.clone();
+
+            tokio::spawn(async move {
+                let stream = match handshake(acceptor, stream, peer).await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        slog::debug!(log, "TLS handshake failed: {:?}", e);
+                        return;
+                    }
+                };
+
+                if !allow.is_empty() {
+                    let names = stream.get_ref().1.peer_certificates()
+                        .and_then(|certs| certs.first())
+                        .map(client_names)
+                        .unwrap_or_default();
+
+                    if !names.iter().any(|name| allow.iter().any(|pattern| pattern.matches(name))) {
+                        stats.rejected_clients.fetch_add(1, Ordering::Relaxed);
+                        slog::warn!(log, "rejecting client not in allowlist: {:?}", names);
+                        return;
+                    }
+                }
+
+                let _ = tx.send(stream).await;
+            });
+        }
+    });
+
+    ReceiverStream::new(rx).map(Ok)
+}
+
+/// errors which only affect the connection being accepted, so the next can
+/// be accepted straight away. as in hyper's `AddrIncoming`
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(e.kind(),
+        | io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::ConnectionReset)
+}
+
+async fn handshake(acceptor: TlsAcceptor, stream: TcpStream, peer: SocketAddr)
+    -> anyhow::Result<TlsStream<TcpStream>>
+{
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await
+        .with_context(|| format!("handshake timed out: {}", peer))?
+        .with_context(|| format!("handshake: {}", peer))
+}
+
+pub async fn load(tls: &config::Tls) -> anyhow::Result<Arc<ServerConfig>> {
+    let cert = read_certs(&tls.cert).await
+        .with_context(|| format!("reading cert: {}", tls.cert.display()))?;
+
+    let key = read_key(&tls.key).await
+        .with_context(|| format!("reading key: {}", tls.key.display()))?;
+
+    let verifier = match &tls.verify {
+        Some(verify) => {
+            let mut roots = RootCertStore::empty();
+
+            let ca = read_certs(&verify.ca).await
+                .with_context(|| format!("reading CA: {}", verify.ca.display()))?;
+
+            for cert in &ca {
+                roots.add(cert)
+                    .with_context(|| format!("adding CA cert: {}", verify.ca.display()))?;
+            }
+
+            AllowAnyAuthenticatedClient::new(roots)
+        }
+        None => NoClientAuth::new(),
+    };
+
+    let mut server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(cert, key)
+        .context("building TLS config")?;
+
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Arc::new(server_config))
+}
+
+/// common name and subject alternative names of a client certificate, the
+/// set of identities matched against the allowlist
+fn client_names(cert: &Certificate) -> Vec<String> {
+    use x509_parser::extensions::GeneralName;
+
+    let Ok((_, cert)) = x509_parser::parse_x509_certificate(&cert.0) else {
+        return Vec::new();
+    };
+
+    let mut names = cert.subject()
+        .iter_common_name()
+        .filter_map(|cn| cn.as_str().ok())
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+
+    if let Ok(Some(san)) = cert.subject_alternative_name() {
+        names.extend(san.value.general_names.iter()
+            .filter_map(|name| match name {
+                | GeneralName::DNSName(name)
+                | GeneralName::RFC822Name(name)
+                | GeneralName::URI(name) => Some(name.to_string()),
+                _ => None,
+            }));
+    }
+
+    names
+}
+
+async fn read_certs(path: &Path) -> anyhow::Result<Vec<Certificate>> {
+    let certs = read_pem(path, rustls_pemfile::certs).await?;
+
+    if certs.is_empty() {
+        anyhow::bail!("no certificates found");
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+async fn read_key(path: &Path) -> anyhow::Result<PrivateKey> {
+    use rustls_pemfile::Item;
+
+    read_pem(path, rustls_pemfile::read_all).await?
+        .into_iter()
+        .find_map(|item| match item {
+            | Item::RSAKey(key)
+            | Item::PKCS8Key(key)
+            | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow::anyhow!("no private key found"))
+}
+
+async fn read_pem<T: Send + 'static>(
+    path: &Path,
+    parse: fn(&mut dyn io::BufRead) -> io::Result<T>,
+) -> anyhow::Result<T> {
+    let path = path.to_owned();
+
+    let result = tokio::task::spawn_blocking(move || {
+        let mut reader = BufReader::new(File::open(path)?);
+        parse(&mut reader)
+    }).await?;
+
+    Ok(result?)
+}
+
+/// modification times of all files making up the TLS config, used to detect
+/// when any of them have been replaced
+async fn modified(tls: &config::Tls) -> Vec<Option<SystemTime>> {
+    let mut paths: Vec<&PathBuf> = vec![&tls.cert, &tls.key];
+    paths.extend(tls.verify.as_ref().map(|verify| &verify.ca));
+
+    let mut stamps = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        let stamp = tokio::fs::metadata(path).await
+            .and_then(|meta| meta.modified())
+            .ok();
+
+        stamps.push(stamp);
+    }
+
+    stamps
+}
@@ -2,25 +2,419 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use regex::Regex;
 use serde::Deserialize;
 use serde::de;
+use zbus::names::OwnedWellKnownName;
+
+use crate::file_gauge::extract::Extract;
+use crate::file_gauge::transform::{Named, Transform};
 
 #[derive(Deserialize)]
 pub struct Config {
+    #[serde(default)]
+    pub dbus: Dbus,
     pub watch: Watch,
     pub gauges: Gauges,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, PartialEq)]
+pub struct Dbus {
+    /// without one, the session bus is tried and then the system bus
+    pub bus: Option<Bus>,
+    /// well-known name to own, such as `org.hails.dprom.FileGauge`
+    pub name: Option<OwnedWellKnownName>,
+    /// take the name from its current owner, if they allow it
+    #[serde(default)]
+    pub replace_existing: bool,
+    /// let others take the name from us, exiting when they do
+    #[serde(default)]
+    pub allow_replacement: bool,
+    /// wait for the name if it's owned, rather than failing to start
+    #[serde(default)]
+    pub queue: bool,
+    /// signal changes to gauges through Gauge1 as well as in DProm2's
+    /// batches. clients which only watch DProm2 don't need them
+    #[serde(default = "default_property_signals")]
+    pub property_signals: bool,
+}
+
+impl Default for Dbus {
+    fn default() -> Self {
+        Dbus {
+            bus: None,
+            name: None,
+            replace_existing: false,
+            allow_replacement: false,
+            queue: false,
+            property_signals: default_property_signals(),
+        }
+    }
+}
+
+/// `"session"`, `"system"`, or `{ address = "unix:path=..." }`
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Bus {
+    Session,
+    System,
+    Address(String),
+}
+
+#[derive(Deserialize, Clone)]
 pub struct Watch {
-    #[serde(deserialize_with = "parse_duration", default = "default_refresh_secs")]
+    #[serde(deserialize_with = "parse_interval", default = "default_refresh_secs")]
     pub refresh_secs: Duration,
+    /// random delay of up to this long added to each refresh, so that
+    /// gauges sharing an interval don't all wake up at once
+    #[serde(deserialize_with = "parse_duration", default)]
+    pub jitter_secs: Duration,
+    /// when set, the refresh interval doubles with each consecutive error
+    /// reading a file, up to this long
+    #[serde(deserialize_with = "parse_optional_duration", default)]
+    pub max_backoff_secs: Option<Duration>,
+    /// default for gauges which don't set their own
+    #[serde(default)]
+    pub mode: WatchMode,
+    /// how often to expand globs in gauge paths again, picking up devices
+    /// which have come or gone
+    #[serde(deserialize_with = "parse_interval", default = "default_rescan_secs")]
+    pub rescan_secs: Duration,
+}
+
+impl Watch {
+    /// the schedule for `gauge`, taking anything it doesn't set itself from
+    /// `[watch]`
+    pub fn schedule(&self, gauge: &Gauge) -> Schedule {
+        Schedule {
+            refresh: gauge.refresh_secs.unwrap_or(self.refresh_secs),
+            jitter: gauge.jitter_secs.unwrap_or(self.jitter_secs),
+            max_backoff: gauge.max_backoff_secs.or(self.max_backoff_secs),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Schedule {
+    pub refresh: Duration,
+    pub jitter: Duration,
+    pub max_backoff: Option<Duration>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// re-read every `refresh_secs`
+    #[default]
+    Poll,
+    /// re-read when inotify reports the file was written or replaced.
+    /// files on procfs and sysfs, which mostly don't report changes, are
+    /// polled instead
+    Inotify,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Gauges(pub HashMap<GaugeName, Gauge>);
+
+impl Gauges {
+    pub fn has_globs(&self) -> bool {
+        self.0.values().any(Gauge::is_glob)
+    }
+}
+
+/// Either just the path of the file, or a table with further options
+/// overriding those in `[watch]`, and choosing how the value is extracted
+/// from the file. Paths may be globs, see `expand`. Instead of a path, a
+/// table may give a command, whose output is read like a file:
+///
+/// ```toml
+/// cpu_temp = "/sys/class/thermal/thermal_zone0/temp"
+/// queue_len = { path = "/run/queue/len", mode = "inotify" }
+/// smart_errors = { path = "/run/smart/errors", refresh_secs = 3600, jitter_secs = 60 }
+/// battery_capacity = { path = "/sys/class/power_supply/*/capacity", transform = "percent" }
+/// mem_available = { path = "/proc/meminfo", key = "MemAvailable", transform = "kilo" }
+/// battery_charging = { path = "/sys/class/power_supply/BAT1/status", map = { Charging = 1, Discharging = 0 } }
+/// rx_bytes = { path = "/sys/class/net/*/statistics/rx_bytes", type = "counter" }
+/// gpu_temp = { command = ["nvidia-smi", "--query-gpu=temperature.gpu", "--format=csv,noheader"], timeout_secs = 5 }
+/// ```
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(try_from = "GaugeEntry")]
+pub struct Gauge {
+    pub source: Source,
+    pub kind: Kind,
+    pub mode: Option<WatchMode>,
+    pub refresh_secs: Option<Duration>,
+    pub jitter_secs: Option<Duration>,
+    pub max_backoff_secs: Option<Duration>,
+    pub extract: Extract,
+    pub transform: Transform,
+}
+
+impl Gauge {
+    pub fn is_glob(&self) -> bool {
+        match &self.source {
+            Source::File(path) => path.to_string_lossy().contains(['*', '?', '[']),
+            Source::Command(_) => false,
+        }
+    }
+}
+
+/// What a gauge entry is served as
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Kind {
+    /// Gauge1, with the value as read
+    #[default]
+    Gauge,
+    /// Counter1, for values which only go up, such as
+    /// `/sys/class/net/*/statistics/rx_bytes`. with `wrap_bits`, a decrease
+    /// which looks like the value wrapping around at 2^wrap_bits is added
+    /// on, rather than passed on as a reset, see `counter::Counter`
+    Counter { wrap_bits: Option<u32> },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Type {
+    Gauge,
+    Counter,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum Source {
+    File(PathBuf),
+    Command(Command),
+}
+
+/// A command run on every refresh, its stdout taken as the file contents.
+/// It is run directly rather than through a shell, inheriting dprom's
+/// environment with `env` added
+#[derive(Clone, PartialEq)]
+pub struct Command {
+    /// the program followed by its arguments, never empty
+    pub argv: Vec<String>,
+    pub env: HashMap<String, String>,
+    /// the command is killed if it hasn't exited within this long
+    pub timeout: Duration,
+}
+
+impl Command {
+    pub fn program(&self) -> &str {
+        &self.argv[0]
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.argv[1..]
+    }
+}
+
+impl Source {
+    /// the path or command line, for logging
+    pub fn describe(&self) -> String {
+        match self {
+            Source::File(path) => path.to_string_lossy().into_owned(),
+            Source::Command(command) => command.argv.join(" "),
+        }
+    }
+}
+
+// only exists while deserializing, so its size doesn't matter
+#[allow(clippy::large_enum_variant)]
+enum GaugeEntry {
+    Path(PathBuf),
+    Table(GaugeTable),
+}
+
+// an untagged enum would do, but its errors don't say which field of the
+// table was wrong, and it accepts fields it doesn't know
+impl<'de> Deserialize<'de> for GaugeEntry {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+        where D: de::Deserializer<'de>
+    {
+        return d.deserialize_any(EntryVisitor);
+
+        struct EntryVisitor;
+
+        impl<'de> de::Visitor<'de> for EntryVisitor {
+            type Value = GaugeEntry;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a path, or a table with a path or command")
+            }
+
+            fn visit_str<E>(self, path: &str) -> Result<GaugeEntry, E>
+                where E: de::Error
+            {
+                Ok(GaugeEntry::Path(PathBuf::from(path)))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<GaugeEntry, A::Error>
+                where A: de::MapAccess<'de>
+            {
+                GaugeTable::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(GaugeEntry::Table)
+            }
+        }
+    }
 }
 
 #[derive(Deserialize)]
-pub struct Gauges(pub HashMap<GaugeName, PathBuf>);
+#[serde(deny_unknown_fields)]
+struct GaugeTable {
+    // exactly one of path and command:
+    path: Option<PathBuf>,
+    command: Option<Vec<String>>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(deserialize_with = "parse_optional_duration", default)]
+    timeout_secs: Option<Duration>,
+    #[serde(rename = "type")]
+    kind: Option<Type>,
+    wrap_bits: Option<u32>,
+    mode: Option<WatchMode>,
+    #[serde(deserialize_with = "parse_optional_interval", default)]
+    refresh_secs: Option<Duration>,
+    #[serde(deserialize_with = "parse_optional_duration", default)]
+    jitter_secs: Option<Duration>,
+    #[serde(deserialize_with = "parse_optional_duration", default)]
+    max_backoff_secs: Option<Duration>,
+    // at most one of regex, key and json_pointer. column indexes the
+    // value found by key, or the first line on its own:
+    regex: Option<String>,
+    key: Option<String>,
+    column: Option<usize>,
+    json_pointer: Option<String>,
+    #[serde(default)]
+    map: HashMap<String, f64>,
+    transform: Option<Named>,
+    scale: Option<f64>,
+    offset: Option<f64>,
+}
+
+impl TryFrom<GaugeEntry> for Gauge {
+    type Error = String;
+
+    fn try_from(entry: GaugeEntry) -> Result<Self, String> {
+        let gauge = match entry {
+            GaugeEntry::Path(path) => Gauge {
+                source: Source::File(path),
+                kind: Kind::Gauge,
+                mode: None,
+                refresh_secs: None,
+                jitter_secs: None,
+                max_backoff_secs: None,
+                extract: Extract::Whole,
+                transform: Transform::default(),
+            },
+            GaugeEntry::Table(table) => {
+                let GaugeTable {
+                    path,
+                    command,
+                    env,
+                    timeout_secs,
+                    kind,
+                    wrap_bits,
+                    mode,
+                    refresh_secs,
+                    jitter_secs,
+                    max_backoff_secs,
+                    regex,
+                    key,
+                    column,
+                    json_pointer,
+                    map,
+                    transform,
+                    scale,
+                    offset,
+                } = table;
+
+                let source = match (path, command) {
+                    (Some(path), None) if env.is_empty() && timeout_secs.is_none() => Source::File(path),
+                    (Some(path), None) => {
+                        return Err(format!("{}: env and timeout_secs only apply to commands", path.display()));
+                    }
+                    (None, Some(argv)) if argv.is_empty() => {
+                        return Err("command must not be empty".to_owned());
+                    }
+                    (None, Some(argv)) => Source::Command(Command {
+                        argv,
+                        env,
+                        timeout: timeout_secs.unwrap_or(default_timeout_secs()),
+                    }),
+                    (Some(_), Some(_)) | (None, None) => {
+                        return Err("gauge needs exactly one of path and command".to_owned());
+                    }
+                };
+
+                // commands are always polled, with inotify set in [watch]
+                // too, but asking for it on one is a mistake:
+                if let (Source::Command(_), Some(WatchMode::Inotify)) = (&source, mode) {
+                    return Err(format!("{}: commands can't be watched with inotify", source.describe()));
+                }
+
+                let kind = match (kind, wrap_bits) {
+                    (None | Some(Type::Gauge), None) => Kind::Gauge,
+                    (Some(Type::Counter), Some(bits)) if !(1..=63).contains(&bits) => {
+                        return Err(format!("{}: wrap_bits must be between 1 and 63", source.describe()));
+                    }
+                    (Some(Type::Counter), wrap_bits) => Kind::Counter { wrap_bits },
+                    (_, Some(_)) => {
+                        return Err(format!("{}: wrap_bits only applies to counters", source.describe()));
+                    }
+                };
 
-#[derive(Deserialize, Hash, PartialEq, Eq, Clone)]
+                let extract = match (regex, key, column, json_pointer) {
+                    (None, None, None, None) => Extract::Whole,
+                    (Some(regex), None, None, None) => {
+                        let regex = Regex::new(&regex)
+                            .map_err(|e| format!("invalid regex for {}: {}", source.describe(), e))?;
+
+                        Extract::Regex(regex)
+                    }
+                    (None, Some(key), column, None) => Extract::Key { key, column: column.unwrap_or(0) },
+                    (None, None, Some(column), None) => Extract::Column(column),
+                    (None, None, None, Some(pointer)) => Extract::JsonPointer(pointer),
+                    _ => {
+                        return Err(format!("{}: regex, key and json_pointer are exclusive, \
+                            and column can only be combined with key", source.describe()));
+                    }
+                };
+
+                Gauge {
+                    source,
+                    kind,
+                    mode,
+                    refresh_secs,
+                    jitter_secs,
+                    max_backoff_secs,
+                    extract,
+                    transform: Transform {
+                        map,
+                        named: transform,
+                        scale: scale.unwrap_or(1.0),
+                        offset: offset.unwrap_or(0.0),
+                    },
+                }
+            }
+        };
+
+        if gauge.is_glob() {
+            let pattern = gauge.source.describe();
+
+            // expanded names are made from the components matched, which
+            // a recursive wildcard doesn't match one to one:
+            if pattern.contains("**") {
+                return Err(format!("{}: recursive wildcards are not supported", pattern));
+            }
+
+            glob::Pattern::new(&pattern)
+                .map_err(|e| format!("invalid glob {}: {}", pattern, e.msg))?;
+        }
+
+        Ok(gauge)
+    }
+}
+
+#[derive(Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[serde(transparent)]
 pub struct GaugeName(#[serde(deserialize_with = "parse_gauge_name")] String);
 
@@ -28,12 +422,37 @@ impl GaugeName {
     pub fn as_str(&self) -> &str {
         &self.0
     }
+
+    /// this name with each of `suffixes` appended after an underscore,
+    /// replacing characters not allowed in names with underscores
+    pub fn with_suffixes<'a>(&self, suffixes: impl IntoIterator<Item = &'a str>) -> GaugeName {
+        let mut name = self.0.clone();
+
+        for suffix in suffixes {
+            name.push('_');
+            name.extend(suffix.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }));
+        }
+
+        GaugeName(name)
+    }
+}
+
+const fn default_timeout_secs() -> Duration {
+    Duration::from_secs(10)
 }
 
 const fn default_refresh_secs() -> Duration {
     Duration::from_secs(5)
 }
 
+const fn default_property_signals() -> bool {
+    true
+}
+
+const fn default_rescan_secs() -> Duration {
+    Duration::from_secs(60)
+}
+
 fn parse_duration<'de, D>(d: D) -> Result<Duration, D::Error>
     where D: de::Deserializer<'de>
 {
@@ -47,6 +466,31 @@ fn parse_duration<'de, D>(d: D) -> Result<Duration, D::Error>
     }
 }
 
+/// as `parse_duration`, for interval periods, which mustn't be zero. values
+/// too small to represent are refused too
+fn parse_interval<'de, D>(d: D) -> Result<Duration, D::Error>
+    where D: de::Deserializer<'de>
+{
+    let duration = f64::deserialize(d)?;
+
+    match Duration::try_from_secs_f64(duration) {
+        Ok(interval) if !interval.is_zero() => Ok(interval),
+        _ => Err(de::Error::invalid_value(de::Unexpected::Float(duration), &"interval must be longer than zero")),
+    }
+}
+
+fn parse_optional_duration<'de, D>(d: D) -> Result<Option<Duration>, D::Error>
+    where D: de::Deserializer<'de>
+{
+    parse_duration(d).map(Some)
+}
+
+fn parse_optional_interval<'de, D>(d: D) -> Result<Option<Duration>, D::Error>
+    where D: de::Deserializer<'de>
+{
+    parse_interval(d).map(Some)
+}
+
 fn parse_gauge_name<'de, D>(d: D) -> Result<String, D::Error>
     where D: de::Deserializer<'de>
 {
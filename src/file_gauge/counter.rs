@@ -0,0 +1,53 @@
+/// Turns the values read from a counter's file into the value served. The
+/// value in the file going down means it either wrapped around, which is
+/// hidden by adding the wrapped range on, or was reset, as when a network
+/// interface is recreated, which is passed on for the exporter to detect.
+#[derive(Default)]
+pub struct Counter {
+    last: Option<u64>,
+    /// the range added on for each wrap seen so far
+    offset: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    /// the value stayed the same or went up
+    Increase,
+    Wrap,
+    Reset,
+}
+
+impl Counter {
+    /// the value to serve after `raw` was read, and how it changed.
+    /// decreases are only taken for a wrap at 2^`wrap_bits` if less than
+    /// half the range would have been counted across it, anything else is a
+    /// reset
+    pub fn observe(&mut self, raw: u64, wrap_bits: Option<u32>) -> (u64, Change) {
+        let change = match (self.last, wrap_bits) {
+            (None, _) => Change::Increase,
+            (Some(last), _) if raw >= last => Change::Increase,
+            (Some(last), Some(bits)) if is_wrap(last, raw, bits) => {
+                self.offset = self.offset.saturating_add(1 << bits);
+                Change::Wrap
+            }
+            (Some(_), _) => {
+                self.offset = 0;
+                Change::Reset
+            }
+        };
+
+        self.last = Some(raw);
+        (self.offset.saturating_add(raw), change)
+    }
+}
+
+fn is_wrap(last: u64, raw: u64, bits: u32) -> bool {
+    let range = 1u64 << bits;
+
+    // values outside the range can't have wrapped at it:
+    if last >= range || raw >= range {
+        return false;
+    }
+
+    range - last + raw < range / 2
+}
@@ -1,9 +1,13 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures::future;
 use tokio::sync::watch;
-use zbus::dbus_interface;
+use zbus::{dbus_interface, SignalContext};
 use zbus::zvariant::{ObjectPath, OwnedObjectPath};
 
-use crate::file_gauge::config::GaugeName;
-use crate::file_gauge::WatchValue;
+use crate::file_gauge::config::{self, GaugeName};
+use crate::file_gauge::{self, WatchValue};
 
 #[derive(Clone)]
 pub struct DProm {
@@ -18,6 +22,100 @@ impl DProm {
     }
 }
 
+/// Batched counterpart to `DProm`, served alongside it at the same path.
+/// only gauges are batched, counters are left for clients to watch
+/// individually
+#[derive(Clone)]
+pub struct DProm2 {
+    pub metrics: Vec<ObjectPath<'static>>,
+    pub gauges: Vec<Gauge>,
+}
+
+#[dbus_interface(name = "org.hails.dprom.DProm2")]
+impl DProm2 {
+    #[dbus_interface(property)]
+    pub fn metrics(&self) -> &[ObjectPath<'static>] {
+        &self.metrics
+    }
+
+    /// Gauge1 objects don't implement Timestamp, so it's always 0
+    pub fn get_values(&self) -> Vec<(ObjectPath<'static>, f64, i64, String)> {
+        self.gauges.iter().map(Gauge::batch_value).collect()
+    }
+
+    #[dbus_interface(signal)]
+    pub async fn values_changed(ctxt: &SignalContext<'_>, values: &[(ObjectPath<'_>, f64, i64, String)]) -> zbus::Result<()>;
+}
+
+impl DProm2 {
+    pub fn gauge(&self, path: &ObjectPath<'_>) -> Option<&Gauge> {
+        self.gauges.iter().find(|gauge| gauge.object_path() == *path)
+    }
+}
+
+/// how long Collect waits for each gauge, so that a slow one can't hold up
+/// the reply past the exporter's timeout
+const COLLECT_READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Pull mode interface, reads every gauge's file on demand. Collect1 only
+/// carries gauges, so counters are left for clients to watch through
+/// Counter1 as in push mode
+#[derive(Clone)]
+pub struct Collect {
+    /// shared with the gauges being served, which are updated through it
+    /// rather than the object server so as not to wait on collections in
+    /// progress
+    pub gauges: Arc<RwLock<Vec<Collected>>>,
+}
+
+/// A gauge as read by Collect
+#[derive(Clone)]
+pub struct Collected {
+    pub name: GaugeName,
+    pub config: config::Gauge,
+    /// the gauge's own reads. commands aren't run again for every Collect,
+    /// which anyone allowed to call it could otherwise trigger at will, and
+    /// report their latest output instead
+    pub watch: watch::Receiver<WatchValue>,
+}
+
+impl Collected {
+    async fn read(&self) -> anyhow::Result<f64> {
+        if let config::Source::Command(_) = self.config.source {
+            let watch = self.watch.borrow();
+
+            return match (&watch.error, watch.last) {
+                (None, Some(value)) => Ok(value),
+                (Some(error), _) => Err(anyhow::anyhow!("{}", error)),
+                (None, None) => Err(anyhow::anyhow!("not read yet")),
+            };
+        }
+
+        tokio::time::timeout(COLLECT_READ_TIMEOUT, file_gauge::read(&self.config)).await
+            .map_err(|_| anyhow::anyhow!("timed out after {:?}", COLLECT_READ_TIMEOUT))?
+    }
+}
+
+#[dbus_interface(name = "org.hails.dprom.Collect1")]
+impl Collect {
+    /// gauges which can't be read are NaN, as in Gauge1, along with why.
+    /// there are no timestamps, so they're always 0
+    pub async fn collect(&self) -> Vec<(String, f64, i64, String)> {
+        let gauges = self.gauges.read().unwrap().clone();
+
+        let reads = gauges.iter().map(|gauge| async move {
+            let name = gauge.name.as_str().to_owned();
+
+            match gauge.read().await {
+                Ok(value) => (name, value, 0, String::new()),
+                Err(e) => (name, f64::NAN, 0, format!("{:#}", e)),
+            }
+        });
+
+        future::join_all(reads).await
+    }
+}
+
 #[derive(Clone)]
 pub struct Gauge {
     pub name: GaugeName,
@@ -31,11 +129,26 @@ impl Gauge {
         self.name.as_str()
     }
 
+    /// NaN while the gauge can't be read, see `error`
     #[dbus_interface(property)]
     pub fn value(&self) -> f64 {
-        self.watch.borrow().clone().unwrap_or_else(|| {
-            0.0
-        })
+        let watch = self.watch.borrow();
+
+        match (&watch.error, watch.last) {
+            (None, Some(value)) => value,
+            _ => f64::NAN,
+        }
+    }
+
+    /// why the gauge couldn't be read, empty if it could
+    #[dbus_interface(property)]
+    pub fn error(&self) -> String {
+        self.watch.borrow().error.clone().unwrap_or_default()
+    }
+
+    #[dbus_interface(property)]
+    pub fn last_success(&self) -> i64 {
+        self.watch.borrow().last_success.unwrap_or(0)
     }
 }
 
@@ -44,11 +157,63 @@ impl Gauge {
         gauge_path(&self.name)
     }
 
+    /// path, value, timestamp and error, as batched in DProm2
+    pub fn batch_value(&self) -> (ObjectPath<'static>, f64, i64, String) {
+        (self.object_path(), self.value(), 0, self.error())
+    }
+
     pub fn watch(&self) -> &watch::Receiver<WatchValue> {
         &self.watch
     }
 }
 
+#[derive(Clone)]
+pub struct Counter {
+    pub name: GaugeName,
+    pub watch: watch::Receiver<WatchValue<u64>>,
+}
+
+#[dbus_interface(name = "org.hails.dprom.Counter1")]
+impl Counter {
+    #[dbus_interface(property)]
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// the last value read, kept while the counter can't be read
+    #[dbus_interface(property)]
+    pub fn value(&self) -> u64 {
+        self.watch.borrow().last.unwrap_or(0)
+    }
+
+    #[dbus_interface(property)]
+    pub fn error(&self) -> String {
+        self.watch.borrow().error.clone().unwrap_or_default()
+    }
+
+    #[dbus_interface(property)]
+    pub fn last_success(&self) -> i64 {
+        self.watch.borrow().last_success.unwrap_or(0)
+    }
+}
+
+impl Counter {
+    pub fn object_path(&self) -> ObjectPath<'static> {
+        counter_path(&self.name)
+    }
+
+    pub fn watch(&self) -> &watch::Receiver<WatchValue<u64>> {
+        &self.watch
+    }
+}
+
+pub fn counter_path(name: &GaugeName) -> ObjectPath<'static> {
+    OwnedObjectPath::try_from(format!("/org/hails/dprom/counter/{}", name.as_str()))
+        // validity is ensured by config::GaugeName:
+        .unwrap()
+        .into_inner()
+}
+
 pub fn gauge_path(name: &GaugeName) -> ObjectPath<'static> {
     OwnedObjectPath::try_from(format!("/org/hails/dprom/gauge/{}", name.as_str()))
         // validity is ensured by config::GaugeName:
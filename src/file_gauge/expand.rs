@@ -0,0 +1,76 @@
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::file_gauge::config::{self, GaugeName};
+
+/// Expands configured gauges into the gauges to serve. A gauge with a glob in
+/// its path becomes one gauge per matching file, named after the configured
+/// name with the path components matched by wildcards appended, so that
+/// `battery_capacity = "/sys/class/power_supply/*/capacity"` becomes
+/// `battery_capacity_BAT0`, `battery_capacity_AC`, and so on. Gauges without
+/// a glob are always included, whether their file exists or not.
+pub async fn expand(log: &slog::Logger, gauges: &config::Gauges) -> anyhow::Result<BTreeMap<GaugeName, config::Gauge>> {
+    let log = log.clone();
+    let gauges = gauges.clone();
+
+    // globbing reads directories synchronously:
+    tokio::task::spawn_blocking(move || expand_blocking(&log, &gauges))
+        .await
+        .context("expanding globs")
+}
+
+fn expand_blocking(log: &slog::Logger, gauges: &config::Gauges) -> BTreeMap<GaugeName, config::Gauge> {
+    let (globs, plain) = gauges.0.iter()
+        .partition::<BTreeMap<_, _>, _>(|(_, gauge)| gauge.is_glob());
+
+    // plain gauges go first, so they win any clash with an expanded name:
+    let mut expanded = plain.into_iter()
+        .map(|(name, gauge)| (name.clone(), gauge.clone()))
+        .collect::<BTreeMap<_, _>>();
+
+    for (name, gauge) in globs {
+        // only file paths are globs:
+        let config::Source::File(pattern_path) = &gauge.source else { continue };
+        let pattern = pattern_path.to_string_lossy();
+
+        // patterns are validated along with the config:
+        let Ok(paths) = glob::glob(&pattern) else { continue };
+
+        for path in paths {
+            let path = match path {
+                Ok(path) => path,
+                Err(e) => {
+                    slog::debug!(log, "error expanding glob: {:?}", e; "glob" => pattern.as_ref());
+                    continue;
+                }
+            };
+
+            let matched = matched_components(pattern_path, &path);
+            let expanded_name = name.with_suffixes(matched.iter().map(String::as_str));
+
+            match expanded.entry(expanded_name) {
+                Entry::Vacant(entry) => {
+                    entry.insert(config::Gauge { source: config::Source::File(path), ..gauge.clone() });
+                }
+                Entry::Occupied(entry) => {
+                    slog::warn!(log, "gauge name from glob already in use, skipping";
+                        "gauge" => entry.key().as_str(), "path" => path.to_string_lossy().as_ref());
+                }
+            }
+        }
+    }
+
+    expanded
+}
+
+/// the components of `path` matched by components of `pattern` containing
+/// wildcards. without recursive wildcards, both have as many components
+fn matched_components(pattern: &Path, path: &Path) -> Vec<String> {
+    pattern.components()
+        .zip(path.components())
+        .filter(|(pattern, _)| pattern.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .map(|(_, component)| component.as_os_str().to_string_lossy().into_owned())
+        .collect()
+}
@@ -0,0 +1,101 @@
+use std::borrow::Cow;
+
+use anyhow::Context;
+use regex::Regex;
+
+/// How a gauge's value is found in the contents of its file
+#[derive(Clone, Debug, Default)]
+pub enum Extract {
+    /// the whole file, trimmed
+    #[default]
+    Whole,
+    /// the first capture group of the first match, or the whole match if
+    /// the regex has no groups
+    Regex(Regex),
+    /// the line `key=value` or `key: value`, as in `/proc/meminfo` and
+    /// `uevent` files. the value is split on whitespace and indexed by
+    /// `column`, to leave out units
+    Key { key: String, column: usize },
+    /// the whitespace separated field at this index on the first line, as in
+    /// `/proc/loadavg`
+    Column(usize),
+    /// the number, boolean or string at this JSON pointer (RFC 6901).
+    /// booleans are read as 0 and 1
+    JsonPointer(String),
+}
+
+// compares regexes by their source, which is enough to tell whether a
+// reloaded gauge changed
+impl PartialEq for Extract {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Extract::Whole, Extract::Whole) => true,
+            (Extract::Regex(a), Extract::Regex(b)) => a.as_str() == b.as_str(),
+            (Extract::Key { key: a, column: a_column }, Extract::Key { key: b, column: b_column }) => {
+                a == b && a_column == b_column
+            }
+            (Extract::Column(a), Extract::Column(b)) => a == b,
+            (Extract::JsonPointer(a), Extract::JsonPointer(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Extract {
+    /// the raw value, for `Transform` to turn into a number
+    pub fn value<'a>(&self, contents: &'a str) -> anyhow::Result<Cow<'a, str>> {
+        let value = match self {
+            Extract::Whole => contents.trim(),
+            Extract::Regex(regex) => {
+                let captures = regex.captures(contents)
+                    .with_context(|| format!("no match for regex {:?}", regex.as_str()))?;
+
+                // group 0 is the whole match, present on any match:
+                captures.get(1).or_else(|| captures.get(0)).unwrap().as_str()
+            }
+            Extract::Key { key, column } => {
+                let value = contents.lines()
+                    .find_map(|line| {
+                        let (line_key, value) = line.split_once(['=', ':'])?;
+                        (line_key.trim() == key).then_some(value)
+                    })
+                    .with_context(|| format!("no line with key {:?}", key))?;
+
+                field(unquote(value.trim()), *column)?
+            }
+            Extract::Column(column) => {
+                let line = contents.lines().next().unwrap_or_default();
+                field(line, *column)?
+            }
+            Extract::JsonPointer(pointer) => {
+                let json = serde_json::from_str::<serde_json::Value>(contents)
+                    .context("parsing JSON")?;
+
+                let value = match json.pointer(pointer) {
+                    Some(serde_json::Value::Number(number)) => number.to_string(),
+                    Some(serde_json::Value::Bool(value)) => if *value { "1" } else { "0" }.to_owned(),
+                    Some(serde_json::Value::String(value)) => value.clone(),
+                    Some(value) => anyhow::bail!("expected number or string at {:?}, found {}", pointer, value),
+                    None => anyhow::bail!("nothing at JSON pointer {:?}", pointer),
+                };
+
+                return Ok(Cow::Owned(value));
+            }
+        };
+
+        Ok(Cow::Borrowed(value))
+    }
+}
+
+fn field(line: &str, column: usize) -> anyhow::Result<&str> {
+    line.split_whitespace()
+        .nth(column)
+        .with_context(|| format!("no column {} in {:?}", column, line))
+}
+
+/// values in files like os-release may be quoted
+fn unquote(value: &str) -> &str {
+    value.strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::ffi::{CString, OsString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use futures::stream::StreamExt;
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use tokio::sync::mpsc;
+
+const EVENT_BUFFER: usize = 4096;
+
+/// One inotify instance shared by every watched file. Each file is watched
+/// through its parent directory, so that files replaced by renaming another
+/// over them keep being watched.
+pub struct Watcher {
+    watches: Arc<Mutex<Watches>>,
+    targets: Arc<Mutex<Targets>>,
+}
+
+type Targets = HashMap<WatchDescriptor, Vec<(OsString, mpsc::UnboundedSender<()>)>>;
+
+impl Watcher {
+    pub fn new(log: slog::Logger) -> io::Result<Self> {
+        let mut events = Inotify::init()?.into_event_stream(vec![0; EVENT_BUFFER])?;
+        let watches = events.watches();
+        let targets = Arc::new(Mutex::new(Targets::new()));
+
+        tokio::spawn({
+            let targets = targets.clone();
+            async move {
+                while let Some(event) = events.next().await {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(e) => {
+                            // dropping every sender lets gauges know to fall
+                            // back to polling
+                            slog::error!(log, "error reading inotify events: {:?}", e);
+                            targets.lock().unwrap().clear();
+                            break;
+                        }
+                    };
+
+                    let targets = targets.lock().unwrap();
+
+                    // the kernel dropped events, so any file may have changed:
+                    if event.mask.contains(EventMask::Q_OVERFLOW) {
+                        slog::warn!(log, "inotify queue overflowed, re-reading all files");
+
+                        for (_, tx) in targets.values().flatten() {
+                            let _ = tx.send(());
+                        }
+
+                        continue;
+                    }
+
+                    let Some(name) = event.name else { continue };
+                    let Some(files) = targets.get(&event.wd) else { continue };
+
+                    for (file_name, tx) in files {
+                        if *file_name == name {
+                            let _ = tx.send(());
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Watcher {
+            watches: Arc::new(Mutex::new(watches)),
+            targets,
+        })
+    }
+
+    /// Returns a channel receiving a message whenever `path` may have
+    /// changed. Bursts of writes are delivered as they come, it's up to the
+    /// receiver to coalesce them.
+    pub fn watch(&self, path: &Path) -> io::Result<Changes> {
+        let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "path has no parent directory"));
+        };
+
+        // relative paths with a single component have an empty parent:
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+
+        // targets are locked first, as when dropping Changes, so the watch
+        // can't be removed between adding it and adding the target:
+        let mut targets = self.targets.lock().unwrap();
+
+        let mask = WatchMask::MODIFY | WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO;
+        let wd = self.watches.lock().unwrap().add(dir, mask)?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        targets.entry(wd.clone())
+            .or_default()
+            .push((file_name.to_owned(), tx));
+
+        Ok(Changes {
+            rx,
+            wd,
+            watches: self.watches.clone(),
+            targets: self.targets.clone(),
+        })
+    }
+}
+
+/// Changes to a watched file. Once the last file watched in a directory is
+/// dropped, the directory stops being watched.
+pub struct Changes {
+    rx: mpsc::UnboundedReceiver<()>,
+    wd: WatchDescriptor,
+    watches: Arc<Mutex<Watches>>,
+    targets: Arc<Mutex<Targets>>,
+}
+
+impl Changes {
+    /// `None` once the watcher has stopped
+    pub async fn recv(&mut self) -> Option<()> {
+        self.rx.recv().await
+    }
+
+    pub fn try_recv(&mut self) -> Result<(), mpsc::error::TryRecvError> {
+        self.rx.try_recv()
+    }
+}
+
+impl Drop for Changes {
+    fn drop(&mut self) {
+        // closing marks our sender closed, among those of other files in
+        // the directory:
+        self.rx.close();
+
+        let mut targets = self.targets.lock().unwrap();
+        let Some(files) = targets.get_mut(&self.wd) else { return };
+
+        files.retain(|(_, tx)| !tx.is_closed());
+
+        if files.is_empty() {
+            targets.remove(&self.wd);
+
+            // fails if the directory was removed, taking the watch with it:
+            let _ = self.watches.lock().unwrap().remove(self.wd.clone());
+        }
+    }
+}
+
+/// Whether `path` is on procfs or sysfs, where files are generated on read
+/// and almost never report changes through inotify
+pub fn is_pseudo_fs(path: &Path) -> io::Result<bool> {
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+
+    // safety: path is nul terminated, and statfs fills in stat on success
+    let stat = unsafe {
+        if libc::statfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        stat.assume_init()
+    };
+
+    Ok(stat.f_type == libc::PROC_SUPER_MAGIC || stat.f_type == libc::SYSFS_MAGIC)
+}
@@ -1,69 +1,279 @@
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
 
 use anyhow::Context;
-use futures::stream::{FuturesUnordered, TryStreamExt};
+use futures::future::{self, BoxFuture, Future, FutureExt};
+use futures::StreamExt;
 use structopt::StructOpt;
-use tokio::sync::watch;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch};
+use zbus::fdo::{RequestNameFlags, RequestNameReply};
+use zbus::zvariant::ObjectPath;
+
+use crate::future::linger::{linger, Linger};
 
 pub mod config;
+pub mod counter;
 pub mod dbus;
+pub mod expand;
+pub mod extract;
+pub mod inotify;
+pub mod transform;
+
+/// The outcome of a gauge's reads so far, `f64` for gauges and `u64` for
+/// counters
+#[derive(Clone, Debug)]
+pub struct WatchValue<T = f64> {
+    /// the value of the last successful read
+    pub last: Option<T>,
+    /// unix milliseconds of the last successful read
+    pub last_success: Option<i64>,
+    /// why the latest read failed, `None` if it succeeded
+    pub error: Option<String>,
+}
+
+impl<T> Default for WatchValue<T> {
+    fn default() -> Self {
+        // keeps the gauge failed, rather than 0, until it's first read:
+        WatchValue { last: None, last_success: None, error: Some("not read yet".to_owned()) }
+    }
+}
+
+/// A value read by `file_watch`
+trait Sample: Clone + Send + Sync + 'static {
+    /// carried from one read to the next
+    type State: Default + Send + 'static;
+
+    fn read<'a>(ctx: &'a SourceCtx, gauge: &'a config::Gauge, state: &'a mut Self::State)
+        -> BoxFuture<'a, anyhow::Result<Self>>;
+}
+
+impl Sample for f64 {
+    type State = ();
+
+    fn read<'a>(_: &'a SourceCtx, gauge: &'a config::Gauge, _: &'a mut ())
+        -> BoxFuture<'a, anyhow::Result<f64>>
+    {
+        read(gauge).boxed()
+    }
+}
+
+impl Sample for u64 {
+    type State = counter::Counter;
+
+    fn read<'a>(ctx: &'a SourceCtx, gauge: &'a config::Gauge, counter: &'a mut counter::Counter)
+        -> BoxFuture<'a, anyhow::Result<u64>>
+    {
+        async move {
+            let raw = read_count(gauge).await?;
+
+            let wrap_bits = match gauge.kind {
+                config::Kind::Counter { wrap_bits } => wrap_bits,
+                config::Kind::Gauge => None,
+            };
+
+            let (value, change) = counter.observe(raw, wrap_bits);
+
+            match change {
+                counter::Change::Increase => {}
+                counter::Change::Wrap => slog::debug!(ctx.log, "counter wrapped"; "raw" => raw),
+                counter::Change::Reset => slog::info!(ctx.log, "counter reset"; "raw" => raw),
+            }
+
+            Ok(value)
+        }.boxed()
+    }
+}
+
+const DPROM_PATH: &str = "/org/hails/dprom";
 
-pub type WatchValue = Option<f64>;
+/// how long to wait for other gauges to change before emitting a batched
+/// DProm2.ValuesChanged signal
+const BATCH_DELAY: Duration = Duration::from_millis(50);
+
+/// how long to wait for further inotify events before re-reading a changed
+/// file
+const COALESCE_DELAY: Duration = Duration::from_millis(50);
 
 struct Ctx {
     log: slog::Logger,
 }
 
 impl Ctx {
-    pub fn with_path(&self, path: PathBuf) -> PathCtx {
-        PathCtx {
-            log: self.log.new(slog::o!("path" => path.to_string_lossy().to_string())),
-            path,
-        }
+    pub fn with_source(&self, source: &config::Source) -> SourceCtx {
+        let log = match source {
+            config::Source::File(_) => self.log.new(slog::o!("path" => source.describe())),
+            config::Source::Command(_) => self.log.new(slog::o!("command" => source.describe())),
+        };
+
+        SourceCtx { log }
     }
 }
 
-struct PathCtx {
+struct SourceCtx {
     log: slog::Logger,
-    path: PathBuf,
 }
 
-fn file_watch(ctx: PathCtx, watch: &config::Watch) -> watch::Receiver<WatchValue> {
-    let (tx, rx) = watch::channel(None);
-    let refresh_secs = watch.refresh_secs;
+fn file_watch<T: Sample>(
+    ctx: SourceCtx,
+    gauge: config::Gauge,
+    schedule: config::Schedule,
+    mut changes: Option<inotify::Changes>,
+) -> (watch::Receiver<WatchValue<T>>, Linger<()>) {
+    let (tx, rx) = watch::channel(WatchValue::default());
+
+    let task = linger(async move {
+        let mut state = T::State::default();
+
+        // consecutive errors reading the gauge, for backoff:
+        let mut errors = 0;
 
-    tokio::spawn(async move {
         loop {
-            match read(&ctx.path).await {
+            let value = match T::read(&ctx, &gauge, &mut state).await {
                 Ok(value) => {
-                    match tx.send(Some(value)) {
-                        Ok(()) => {}
-                        Err(_) => {
-                            // other side closed
-                            break;
-                        }
-                    }
+                    errors = 0;
+                    WatchValue { last: Some(value), last_success: Some(unix_millis()), error: None }
                 }
                 Err(e) => {
-                    slog::error!(ctx.log, "error reading file: {:?}", e);
+                    errors += 1;
+                    slog::error!(ctx.log, "error reading gauge: {:?}", e);
+
+                    // the last value is kept for counters, gauges report
+                    // NaN while there's an error:
+                    WatchValue { error: Some(format!("{:#}", e)), ..tx.borrow().clone() }
                 }
+            };
+
+            if tx.send(value).is_err() {
+                // other side closed
+                break;
+            }
+
+            let Some(rx) = &mut changes else {
+                tokio::time::sleep(refresh_delay(&schedule, errors)).await;
+                continue;
+            };
+
+            // while in error, such as when the file is yet to be created,
+            // retry on the usual schedule too rather than only on a change:
+            let changed = match errors {
+                0 => rx.recv().await,
+                _ => match tokio::time::timeout(refresh_delay(&schedule, errors), rx.recv()).await {
+                    Ok(changed) => changed,
+                    Err(_) => { continue; }
+                },
+            };
+
+            if changed.is_none() {
+                slog::warn!(ctx.log, "inotify watch stopped, polling instead");
+                changes = None;
+                continue;
             }
 
-            tokio::time::sleep(refresh_secs).await;
+            // writers often produce several events per update, read once
+            // they've settled:
+            tokio::time::sleep(COALESCE_DELAY).await;
+            while rx.try_recv().is_ok() {}
         }
+    });
 
-        async fn read(path: &Path) -> anyhow::Result<f64> {
-            let value = tokio::fs::read_to_string(path)
-                .await?
-                .trim()
-                .parse()?;
+    (rx, task)
+}
 
-            Ok(value)
+fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|since| since.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// how long to wait before refreshing a gauge, after `errors` consecutive
+/// errors reading it
+fn refresh_delay(schedule: &config::Schedule, errors: u32) -> Duration {
+    let mut delay = schedule.refresh;
+
+    if let (Some(max_backoff), true) = (schedule.max_backoff, errors > 0) {
+        // the first error retries at the usual interval. saturates well
+        // before the shift could overflow:
+        let backoff = delay.saturating_mul(1 << (errors - 1).min(16));
+        delay = backoff.min(max_backoff).max(schedule.refresh);
+    }
+
+    if !schedule.jitter.is_zero() {
+        delay += schedule.jitter.mul_f64(rand::random::<f64>());
+    }
+
+    delay
+}
+
+/// Subscribes to changes to the gauge's file, or returns `None` if it
+/// should be polled instead
+fn inotify_changes(ctx: &SourceCtx, path: &Path, watcher: &inotify::Watcher) -> Option<inotify::Changes> {
+    // a missing file can't be checked, but will most likely be created on
+    // a regular filesystem:
+    if let Ok(true) = inotify::is_pseudo_fs(path) {
+        slog::debug!(ctx.log, "polling file on procfs or sysfs");
+        return None;
+    }
+
+    match watcher.watch(path) {
+        Ok(changes) => Some(changes),
+        Err(e) => {
+            slog::warn!(ctx.log, "error watching file, polling instead: {:?}", e);
+            None
         }
-    });
+    }
+}
+
+pub async fn read(gauge: &config::Gauge) -> anyhow::Result<f64> {
+    let contents = read_source(&gauge.source).await?;
+    let value = gauge.extract.value(&contents)?;
+    let value = gauge.transform.apply(&value)?;
+
+    Ok(value)
+}
+
+/// as `read`, for counters
+pub async fn read_count(gauge: &config::Gauge) -> anyhow::Result<u64> {
+    let contents = read_source(&gauge.source).await?;
+    let value = gauge.extract.value(&contents)?;
+    let value = gauge.transform.apply_count(&value)?;
+
+    Ok(value)
+}
+
+async fn read_source(source: &config::Source) -> anyhow::Result<String> {
+    match source {
+        config::Source::File(path) => Ok(tokio::fs::read_to_string(path).await?),
+        config::Source::Command(command) => run_command(command).await,
+    }
+}
+
+/// Runs a command gauge's command, returning its stdout. Each gauge reads in
+/// a task of its own, so a slow command only holds up its own gauge, and is
+/// killed once it times out
+async fn run_command(command: &config::Command) -> anyhow::Result<String> {
+    let output = tokio::process::Command::new(command.program())
+        .args(command.args())
+        .envs(&command.env)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    let output = tokio::time::timeout(command.timeout, output).await
+        .map_err(|_| anyhow::anyhow!("{} timed out after {:?}", command.program(), command.timeout))?
+        .with_context(|| format!("running {}", command.program()))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{} failed with {}: {:?}", command.program(), output.status, stderr.trim());
+    }
 
-    rx
+    String::from_utf8(output.stdout)
+        .with_context(|| format!("output of {} is not UTF-8", command.program()))
 }
 
 #[derive(StructOpt, Debug)]
@@ -76,77 +286,508 @@ pub async fn run(log: slog::Logger, opt: Opt) -> anyhow::Result<()> {
     let config = config::open(&opt.config).await
         .with_context(|| "config::open")?;
 
-    let ctx = Ctx { log: log.clone() };
+    let shutdown = crate::shutdown::listen(log.clone())?;
 
-    // create Gauge models
-    let gauges = config.gauges.0.into_iter()
-        .map(move |(name, path)| {
-            let watch = file_watch(ctx.with_path(path.clone()), &config.watch);
-            dbus::Gauge { name, watch }
-        })
-        .collect::<Vec<_>>();
+    let mut hangup = signal(SignalKind::hangup())
+        .context("installing SIGHUP handler")?;
 
-    let metric_paths = gauges.iter()
-        .map(|g| g.object_path())
-        .collect::<Vec<_>>();
+    let watcher = needs_inotify(&config)
+        .then(|| inotify::Watcher::new(log.clone()))
+        .transpose()
+        .with_context(|| "inotify::Watcher::new")?;
 
-    // create DProm model
-    let dprom = dbus::DProm {
-        metrics: metric_paths.clone(),
-    };
+    // unlike gauges, the connection is kept as it is on reload:
+    let dbus_config = config.dbus;
+
+    // connect to dbus and serve objects, gauges are added to them below
+    let dprom = dbus::DProm { metrics: Vec::new() };
+    let dprom2 = dbus::DProm2 { metrics: Vec::new(), gauges: Vec::new() };
+    let collected = Arc::new(RwLock::new(Vec::new()));
+    let collect = dbus::Collect { gauges: collected.clone() };
 
-    // connect to dbus and serve objects
-    let conn = Arc::new(serve_objects(&log, dprom, gauges).await
+    let conn = Arc::new(serve_objects(&log, &dbus_config, dprom, dprom2, collect).await
         .with_context(|| "serve_objects")?);
 
-    if metric_paths.is_empty() {
+    let (changed_tx, changed_rx) = mpsc::unbounded_channel();
+    let (error_tx, mut error_rx) = mpsc::unbounded_channel();
+
+    let mut gauges = Gauges {
+        ctx: Ctx { log: log.clone() },
+        conn: conn.clone(),
+        property_signals: dbus_config.property_signals,
+        collected,
+        watch: config.watch,
+        configured: config.gauges,
+        watcher,
+        changed_tx,
+        error_tx,
+        running: BTreeMap::new(),
+    };
+
+    gauges.rescan().await
+        .with_context(|| "serving gauges")?;
+
+    if gauges.running.is_empty() {
         slog::warn!(log, "No gauges configured");
     }
 
-    // spawn refresh tasks
-    metric_paths.iter()
-        .map(|path| {
-            let conn = conn.clone();
-            async move {
-                let interface = conn
-                    .object_server()
-                    .interface::<_, dbus::Gauge>(path)
-                    .await?;
+    // claimed once gauges are served, so that anyone looking the name up
+    // finds them:
+    let name_lost = request_name(&log, &conn, &dbus_config).await
+        .with_context(|| "request_name")?;
+    futures::pin_mut!(name_lost);
+
+    let batch = batch_changes(&conn, changed_rx);
+    futures::pin_mut!(batch);
+
+    let shutdown = shutdown.wait();
+    futures::pin_mut!(shutdown);
+
+    let mut rescan = rescan_interval(gauges.watch.rescan_secs);
+
+    loop {
+        tokio::select! {
+            // gauges keep the changes channel open, so this only returns
+            // on error:
+            result = &mut batch => { return result; }
+            Some(e) = error_rx.recv() => { return Err(e); }
+            // only globs can expand differently on another scan:
+            _ = rescan.tick(), if gauges.configured.has_globs() => {
+                gauges.rescan().await
+                    .with_context(|| "updating gauges")?;
+            }
+            Some(()) = hangup.recv() => {
+                slog::info!(log, "received SIGHUP, reloading config");
+
+                // a broken config leaves the running gauges as they are:
+                let config = match config::open(&opt.config).await {
+                    Ok(config) => config,
+                    Err(e) => {
+                        slog::error!(log, "error reloading config: {:?}", e);
+                        continue;
+                    }
+                };
+
+                if config.dbus != dbus_config {
+                    slog::warn!(log, "changes to [dbus] take effect on restart");
+                }
+
+                gauges.reload(config).await
+                    .with_context(|| "reloading gauges")?;
+
+                rescan = rescan_interval(gauges.watch.rescan_secs);
+            }
+            () = &mut name_lost => {
+                slog::warn!(log, "well-known name taken over, shutting down");
+                break;
+            }
+            () = &mut shutdown => { break; }
+        }
+    }
+
+    // unregister objects before the connection is dropped, so that clients
+    // see them go away before our name is released:
+    gauges.update(BTreeMap::new()).await
+        .with_context(|| "removing gauges")?;
+
+    unserve_objects(&conn).await
+        .with_context(|| "unserve_objects")?;
+
+    if let Some(name) = &dbus_config.name {
+        conn.release_name(name.as_ref()).await
+            .with_context(|| "release_name")?;
+    }
+
+    slog::info!(log, "shut down");
+    Ok(())
+}
+
+/// whether any gauge in `config` is watched with inotify
+fn needs_inotify(config: &config::Config) -> bool {
+    config.gauges.0.values()
+        .filter(|gauge| matches!(gauge.source, config::Source::File(_)))
+        .any(|gauge| gauge.mode.unwrap_or(config.watch.mode) == config::WatchMode::Inotify)
+}
+
+/// ticks every `period`, starting one period from now
+fn rescan_interval(period: Duration) -> tokio::time::Interval {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval
+}
+
+/// Gauges currently read and served on the bus
+struct Gauges {
+    ctx: Ctx,
+    conn: Arc<zbus::Connection>,
+    /// whether gauges signal changes through Gauge1 as well as DProm2
+    property_signals: bool,
+    /// the gauges read by Collect1
+    collected: Arc<RwLock<Vec<dbus::Collected>>>,
+    watch: config::Watch,
+    /// the gauges as configured, before globs are expanded
+    configured: config::Gauges,
+    watcher: Option<inotify::Watcher>,
+    changed_tx: mpsc::UnboundedSender<ObjectPath<'static>>,
+    error_tx: mpsc::UnboundedSender<anyhow::Error>,
+    running: BTreeMap<config::GaugeName, Running>,
+}
+
+/// A served gauge, whose tasks stop when dropped
+struct Running {
+    object: Object,
+    config: config::Gauge,
+    schedule: config::Schedule,
+    mode: config::WatchMode,
+    _refresh: Linger<()>,
+    _watch: Linger<()>,
+}
+
+/// A served Gauge1 or Counter1 object
+enum Object {
+    Gauge(dbus::Gauge),
+    Counter(dbus::Counter),
+}
+
+impl Object {
+    fn object_path(&self) -> ObjectPath<'static> {
+        match self {
+            Object::Gauge(gauge) => gauge.object_path(),
+            Object::Counter(counter) => counter.object_path(),
+        }
+    }
+}
+
+impl Gauges {
+    /// Expands the configured gauges again and serves the result
+    async fn rescan(&mut self) -> anyhow::Result<()> {
+        let expanded = expand::expand(&self.ctx.log, &self.configured).await?;
+        self.update(expanded).await
+    }
+
+    /// Switches to a newly read config, restarting gauges whose config
+    /// changed and leaving the rest running
+    async fn reload(&mut self, config: config::Config) -> anyhow::Result<()> {
+        if self.watcher.is_none() && needs_inotify(&config) {
+            // without a watcher, gauges are polled:
+            match inotify::Watcher::new(self.ctx.log.clone()) {
+                Ok(watcher) => self.watcher = Some(watcher),
+                Err(e) => slog::warn!(self.ctx.log, "error setting up inotify, polling instead: {:?}", e),
+            }
+        }
+
+        self.watch = config.watch;
+        self.configured = config.gauges;
+        self.rescan().await
+    }
+
+    /// Serves exactly the gauges in `expanded`, adding and removing Gauge1
+    /// and Counter1 objects and signalling the change in DProm's metrics as
+    /// needed
+    async fn update(&mut self, mut expanded: BTreeMap<config::GaugeName, config::Gauge>) -> anyhow::Result<()> {
+        let object_server = self.conn.object_server();
+
+        // gauges whose config changed are restarted by removing and
+        // adding them again:
+        let removed = self.running.iter()
+            .filter(|(name, running)| match expanded.get(*name) {
+                Some(gauge) => !self.is_current(running, gauge),
+                None => true,
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        let added = expanded.keys()
+            .filter(|name| !self.running.contains_key(*name) || removed.contains(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if removed.is_empty() && added.is_empty() {
+            return Ok(());
+        }
+
+        for name in removed {
+            let Some(running) = self.running.remove(&name) else { continue };
+            let path = running.object.object_path();
+            let is_counter = matches!(running.object, Object::Counter(_));
+
+            // stop tasks first, so they don't see the object go away:
+            drop(running);
+
+            match is_counter {
+                false => object_server.remove::<dbus::Gauge, _>(&path).await?,
+                true => object_server.remove::<dbus::Counter, _>(&path).await?,
+            };
 
-                let mut watch = interface.get().await.watch().clone();
+            slog::info!(self.ctx.log, "removed gauge"; "gauge" => name.as_str());
+        }
+
+        for name in added {
+            let Some(gauge) = expanded.remove(&name) else { continue };
+            let ctx = self.ctx.with_source(&gauge.source);
+            let schedule = self.watch.schedule(&gauge);
+            let mode = gauge.mode.unwrap_or(self.watch.mode);
 
-                loop {
-                    watch.changed().await?;
+            // commands are always polled:
+            let changes = match (&gauge.source, mode, &self.watcher) {
+                (config::Source::File(path), config::WatchMode::Inotify, Some(watcher)) => {
+                    inotify_changes(&ctx, path, watcher)
+                }
+                _ => None,
+            };
 
-                    let signal_context = interface.signal_context();
+            slog::info!(ctx.log, "serving gauge"; "gauge" => name.as_str());
 
-                    interface.get().await
-                        .value_changed(signal_context)
-                        .await?;
+            let config = gauge.clone();
+
+            let (object, watch_task) = match gauge.kind {
+                config::Kind::Gauge => {
+                    let (watch, watch_task) = file_watch(ctx, gauge, schedule, changes);
+                    let gauge = dbus::Gauge { name: name.clone(), watch };
+                    object_server.at(&gauge.object_path(), gauge.clone()).await?;
+                    (Object::Gauge(gauge), watch_task)
+                }
+                config::Kind::Counter { .. } => {
+                    let (watch, watch_task) = file_watch(ctx, gauge, schedule, changes);
+                    let counter = dbus::Counter { name: name.clone(), watch };
+                    object_server.at(&counter.object_path(), counter.clone()).await?;
+                    (Object::Counter(counter), watch_task)
                 }
+            };
+
+            let refresh_task = linger({
+                let conn = self.conn.clone();
+                let path = object.object_path();
+                // counters aren't batched:
+                let changed_tx = matches!(object, Object::Gauge(_)).then(|| self.changed_tx.clone());
+                let property_signals = self.property_signals;
+                let error_tx = self.error_tx.clone();
+                async move {
+                    let result = match changed_tx {
+                        Some(changed_tx) => refresh(&conn, &path, property_signals, changed_tx).await,
+                        None => refresh_counter(&conn, &path).await,
+                    };
+
+                    if let Err(e) = result {
+                        let _ = error_tx.send(e.context(format!("refreshing {}", path)));
+                    }
+                }
+            });
+
+            self.running.insert(name, Running {
+                object,
+                config,
+                schedule,
+                mode,
+                _refresh: refresh_task,
+                _watch: watch_task,
+            });
+        }
+
+        let dprom = object_server.interface::<_, dbus::DProm>(DPROM_PATH).await?;
+        let dprom2 = object_server.interface::<_, dbus::DProm2>(DPROM_PATH).await?;
+
+        let metrics = self.running.values()
+            .map(|running| running.object.object_path())
+            .collect::<Vec<_>>();
+
+        dprom.get_mut().await.metrics = metrics.clone();
+        dprom2.get_mut().await.metrics = metrics;
+
+        dprom2.get_mut().await.gauges = self.running.values()
+            .filter_map(|running| match &running.object {
+                Object::Gauge(gauge) => Some(gauge.clone()),
+                Object::Counter(_) => None,
+            })
+            .collect();
+
+        *self.collected.write().unwrap() = self.running.iter()
+            .filter_map(|(name, running)| match &running.object {
+                Object::Gauge(gauge) => Some(dbus::Collected {
+                    name: name.clone(),
+                    config: running.config.clone(),
+                    watch: gauge.watch().clone(),
+                }),
+                Object::Counter(_) => None,
+            })
+            .collect();
+
+        dprom.get().await.metrics_changed(dprom.signal_context()).await?;
+        dprom2.get().await.metrics_changed(dprom2.signal_context()).await?;
+
+        Ok(())
+    }
+
+    /// whether `running` was started with the config `gauge` now has
+    fn is_current(&self, running: &Running, gauge: &config::Gauge) -> bool {
+        running.config == *gauge
+            && running.schedule == self.watch.schedule(gauge)
+            && running.mode == gauge.mode.unwrap_or(self.watch.mode)
+    }
+}
+
+/// Passes each change to a gauge on to be batched for DProm2, and unless
+/// `property_signals` is off also signals it individually through Gauge1
+async fn refresh(
+    conn: &zbus::Connection,
+    path: &ObjectPath<'static>,
+    property_signals: bool,
+    changed_tx: mpsc::UnboundedSender<ObjectPath<'static>>,
+) -> anyhow::Result<()> {
+    let interface = conn
+        .object_server()
+        .interface::<_, dbus::Gauge>(path)
+        .await?;
+
+    let mut watch = interface.get().await.watch().clone();
+
+    loop {
+        watch.changed().await?;
+
+        if property_signals {
+            let signal_context = interface.signal_context();
+            let gauge = interface.get().await;
+
+            // Error and LastSuccess must change no later than Value:
+            gauge.error_changed(signal_context).await?;
+            gauge.last_success_changed(signal_context).await?;
+            gauge.value_changed(signal_context).await?;
+        }
+
+        let _ = changed_tx.send(path.clone());
+    }
+}
+
+/// Signals each change to a counter through Counter1
+async fn refresh_counter(conn: &zbus::Connection, path: &ObjectPath<'static>) -> anyhow::Result<()> {
+    let interface = conn
+        .object_server()
+        .interface::<_, dbus::Counter>(path)
+        .await?;
+
+    let mut watch = interface.get().await.watch().clone();
+
+    loop {
+        watch.changed().await?;
+
+        let signal_context = interface.signal_context();
+        let counter = interface.get().await;
+
+        // Error and LastSuccess must change no later than Value:
+        counter.error_changed(signal_context).await?;
+        counter.last_success_changed(signal_context).await?;
+        counter.value_changed(signal_context).await?;
+    }
+}
+
+async fn unserve_objects(conn: &zbus::Connection) -> zbus::Result<()> {
+    let object_server = conn.object_server();
+
+    object_server.remove::<dbus::Collect, _>(DPROM_PATH).await?;
+    object_server.remove::<dbus::DProm2, _>(DPROM_PATH).await?;
+    object_server.remove::<dbus::DProm, _>(DPROM_PATH).await?;
+
+    Ok(())
+}
+
+async fn batch_changes(
+    conn: &zbus::Connection,
+    mut changed_rx: mpsc::UnboundedReceiver<ObjectPath<'static>>,
+) -> anyhow::Result<()> {
+    let interface = conn
+        .object_server()
+        .interface::<_, dbus::DProm2>(DPROM_PATH)
+        .await?;
+
+    while let Some(path) = changed_rx.recv().await {
+        // gauges sharing a refresh interval change at nearly the same time,
+        // give them a chance to join this batch
+        tokio::time::sleep(BATCH_DELAY).await;
+
+        let mut paths = vec![path];
+        while let Ok(path) = changed_rx.try_recv() {
+            if !paths.contains(&path) {
+                paths.push(path);
             }
-        })
-        .collect::<FuturesUnordered<_>>()
-        .try_collect::<()>()
-        .await
+        }
+
+        let values = {
+            let dprom2 = interface.get().await;
+            paths.into_iter()
+                .filter_map(|path| dprom2.gauge(&path).map(dbus::Gauge::batch_value))
+                .collect::<Vec<_>>()
+        };
+
+        dbus::DProm2::values_changed(interface.signal_context(), &values).await?;
+    }
+
+    Ok(())
+}
+
+/// Requests the configured well-known name, if any. The returned future
+/// completes if the name is taken over by another connection, which only
+/// happens with `allow_replacement`
+async fn request_name(
+    log: &slog::Logger,
+    conn: &zbus::Connection,
+    config: &config::Dbus,
+) -> anyhow::Result<impl Future<Output = ()>> {
+    let Some(name) = &config.name else {
+        return Ok(future::Either::Left(future::pending()));
+    };
+
+    // open the stream before requesting, so the signal can't be missed.
+    // we own no other names, so any NameLost is for this one:
+    let mut name_lost = zbus::fdo::DBusProxy::new(conn).await?
+        .receive_name_lost().await?;
+
+    let flags = [
+        (config.replace_existing, RequestNameFlags::ReplaceExisting),
+        (config.allow_replacement, RequestNameFlags::AllowReplacement),
+        (!config.queue, RequestNameFlags::DoNotQueue),
+    ];
+
+    let flags = flags.into_iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, flag)| flag)
+        .collect();
+
+    let reply = conn.request_name_with_flags(name.as_ref(), flags).await
+        .with_context(|| format!("requesting name {}", name))?;
+
+    match reply {
+        RequestNameReply::InQueue => slog::info!(log, "waiting for name {} to be released", name),
+        _ => slog::info!(log, "owning name {}", name),
+    }
+
+    Ok(future::Either::Right(async move {
+        name_lost.next().await;
+    }))
 }
 
 async fn serve_objects(
     log: &slog::Logger,
+    config: &config::Dbus,
     dprom: dbus::DProm,
-    gauges: Vec<dbus::Gauge>,
+    dprom2: dbus::DProm2,
+    collect: dbus::Collect,
 ) -> anyhow::Result<zbus::Connection> {
-    let builders = [
-        ("session", zbus::ConnectionBuilder::session()),
-        ("system", zbus::ConnectionBuilder::system()),
-    ];
+    let builders = match &config.bus {
+        None => vec![
+            ("session", zbus::ConnectionBuilder::session()),
+            ("system", zbus::ConnectionBuilder::system()),
+        ],
+        Some(config::Bus::Session) => vec![("session", zbus::ConnectionBuilder::session())],
+        Some(config::Bus::System) => vec![("system", zbus::ConnectionBuilder::system())],
+        Some(config::Bus::Address(address)) => vec![("address", zbus::ConnectionBuilder::address(address.as_str()))],
+    };
 
     let mut last_error = None;
 
     for (conn_kind, builder) in builders {
         slog::trace!(log, "trying {} dbus", conn_kind);
 
-        let result = try_connection(builder, dprom.clone(), gauges.clone())
+        let result = try_connection(builder, dprom.clone(), dprom2.clone(), collect.clone())
             .await
             .with_context(|| format!("try_connection: {}", conn_kind));
 
@@ -172,20 +813,18 @@ async fn serve_objects(
     async fn try_connection<'a>(
         builder: zbus::Result<zbus::ConnectionBuilder<'a>>,
         dprom: dbus::DProm,
-        gauges: Vec<dbus::Gauge>,
+        dprom2: dbus::DProm2,
+        collect: dbus::Collect,
     ) -> anyhow::Result<zbus::Connection> {
         let conn = builder
             .with_context(|| "build connection")?
-            .serve_at("/org/hails/dprom", dprom.clone())
+            .serve_at(DPROM_PATH, dprom.clone())
+            .with_context(|| "serve_at")?
+            .serve_at(DPROM_PATH, dprom2)
+            .with_context(|| "serve_at")?
+            .serve_at(DPROM_PATH, collect)
             .with_context(|| "serve_at")?;
 
-        let conn = gauges.into_iter()
-            .try_fold(conn, |conn, gauge| {
-                let path = gauge.object_path();
-                conn.serve_at(&path, gauge)
-                    .with_context(|| format!("serve gauge: {}", path))
-            })?;
-
         Ok(conn.build().await?)
     }
 }
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+/// Turns a gauge's raw value into the number exported, so units don't have
+/// to be converted in PromQL. In order: strings in `map` are looked up,
+/// anything else is parsed, then `named` is applied, and finally
+/// `value * scale + offset`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transform {
+    /// numbers for non-numeric values, such as `Charging` in a power supply
+    /// status
+    pub map: HashMap<String, f64>,
+    pub named: Option<Named>,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Named {
+    /// divides by 10^9, as for nanoseconds
+    Nano,
+    /// divides by 10^6, as for sysfs charge in µAh or voltage in µV
+    Micro,
+    /// divides by 1000, as for sysfs temperatures in millidegrees
+    Milli,
+    /// multiplies by 1000, as for kB in `/proc/meminfo`
+    Kilo,
+    /// divides by 100, making a percentage a ratio
+    Percent,
+    /// reads `true`/`false`, `yes`/`no`, `on`/`off` and
+    /// `enabled`/`disabled` as 1 and 0, ignoring case
+    Bool,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            map: HashMap::new(),
+            named: None,
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Transform {
+    pub fn apply(&self, value: &str) -> anyhow::Result<f64> {
+        let value = value.trim();
+
+        let number = match (self.map.get(value), self.named) {
+            (Some(number), _) => *number,
+            (None, Some(Named::Bool)) => parse_bool(value)
+                .map(Ok)
+                .unwrap_or_else(|| parse(value))?,
+            (None, _) => parse(value)?,
+        };
+
+        let number = match self.named {
+            None | Some(Named::Bool) => number,
+            Some(Named::Nano) => number / 1e9,
+            Some(Named::Micro) => number / 1e6,
+            Some(Named::Milli) => number / 1e3,
+            Some(Named::Kilo) => number * 1e3,
+            Some(Named::Percent) => number / 100.0,
+        };
+
+        Ok(number * self.scale + self.offset)
+    }
+
+    /// as `apply`, for counters. without any transform, values are parsed
+    /// as integers, so that counters beyond 2^53 stay exact
+    pub fn apply_count(&self, value: &str) -> anyhow::Result<u64> {
+        if *self == Transform::default() {
+            let value = value.trim();
+            return value.parse()
+                .with_context(|| format!("parsing {:?} as a count", value));
+        }
+
+        let number = self.apply(value)?;
+
+        if !(number.is_finite() && number >= 0.0) {
+            anyhow::bail!("{} is not a valid count", number);
+        }
+
+        // any fraction left by the transform is dropped:
+        Ok(number as u64)
+    }
+}
+
+fn parse_bool(value: &str) -> Option<f64> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "enabled" => Some(1.0),
+        "false" | "no" | "off" | "disabled" => Some(0.0),
+        _ => None,
+    }
+}
+
+fn parse(value: &str) -> anyhow::Result<f64> {
+    value.parse()
+        .with_context(|| format!("parsing {:?} as a number", value))
+}
@@ -2,3 +2,4 @@ pub mod dbus;
 pub mod export;
 pub mod file_gauge;
 pub mod future;
+pub mod shutdown;
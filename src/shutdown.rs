@@ -0,0 +1,52 @@
+use anyhow::Context;
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::watch;
+
+/// Completes once SIGTERM or SIGINT has been received. Every clone observes
+/// the same signal.
+#[derive(Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+}
+
+/// Installs SIGTERM and SIGINT handlers. A second signal while shutting down
+/// exits immediately, for when a graceful shutdown hangs.
+pub fn listen(log: slog::Logger) -> anyhow::Result<Shutdown> {
+    let mut terminate = signal(SignalKind::terminate())
+        .context("installing SIGTERM handler")?;
+
+    let mut interrupt = signal(SignalKind::interrupt())
+        .context("installing SIGINT handler")?;
+
+    let (tx, rx) = watch::channel(false);
+
+    tokio::spawn(async move {
+        let name = received(&mut terminate, &mut interrupt).await;
+        slog::info!(log, "received {}, shutting down", name);
+        let _ = tx.send(true);
+
+        let name = received(&mut terminate, &mut interrupt).await;
+        slog::warn!(log, "received {} while shutting down, exiting now", name);
+        std::process::exit(1);
+    });
+
+    Ok(Shutdown { rx })
+}
+
+async fn received(terminate: &mut Signal, interrupt: &mut Signal) -> &'static str {
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    }
+}
+
+impl Shutdown {
+    pub async fn wait(mut self) {
+        while !*self.rx.borrow() {
+            if self.rx.changed().await.is_err() {
+                // the signal task never exits without sending first
+                return;
+            }
+        }
+    }
+}
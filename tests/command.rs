@@ -0,0 +1,35 @@
+use std::time::{Duration, Instant};
+
+mod common;
+use common::{config, gauge};
+
+#[tokio::test]
+async fn output_extracted() {
+    let config = config(r#"
+        whole = { command = ["echo", "42"] }
+        column = { command = ["sh", "-c", "echo $A $B"], env = { A = "1", B = "2500" }, column = 1, transform = "milli" }
+        key = { command = ["printf", "temp: 48500\nfan: 1200\n"], key = "fan" }
+    "#);
+
+    assert_eq!(dprom::file_gauge::read(&gauge(&config, "whole")).await.unwrap(), 42.0);
+    assert_eq!(dprom::file_gauge::read(&gauge(&config, "column")).await.unwrap(), 2.5);
+    assert_eq!(dprom::file_gauge::read(&gauge(&config, "key")).await.unwrap(), 1200.0);
+}
+
+#[tokio::test]
+async fn failures() {
+    let config = config(r#"
+        status = { command = ["sh", "-c", "echo 1; echo oops >&2; exit 3"] }
+        missing = { command = ["/nonexistent/dprom-test"] }
+        slow = { command = ["sleep", "10"], timeout_secs = 0.2 }
+    "#);
+
+    let e = dprom::file_gauge::read(&gauge(&config, "status")).await.unwrap_err();
+    assert!(format!("{:#}", e).contains("oops"));
+
+    assert!(dprom::file_gauge::read(&gauge(&config, "missing")).await.is_err());
+
+    let start = Instant::now();
+    assert!(dprom::file_gauge::read(&gauge(&config, "slow")).await.is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
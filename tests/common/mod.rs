@@ -0,0 +1,26 @@
+//! helpers shared by the integration tests, not all of which use every one
+#![allow(dead_code)]
+
+use dprom::file_gauge::config::{Config, Gauge};
+
+pub fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).unwrap()
+}
+
+/// parses a config made of `gauges` under `[gauges]`, with `[watch]` left
+/// to its defaults
+pub fn parse(gauges: &str) -> Result<Config, toml::de::Error> {
+    toml::from_str(&format!("[watch]\n[gauges]\n{}", gauges))
+}
+
+pub fn config(gauges: &str) -> Config {
+    parse(gauges).unwrap()
+}
+
+pub fn gauge(config: &Config, name: &str) -> Gauge {
+    config.gauges.0.iter()
+        .find(|(gauge, _)| gauge.as_str() == name)
+        .map(|(_, gauge)| gauge.clone())
+        .unwrap()
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use dprom::file_gauge::config::{Config, Kind, Source};
+
+mod common;
+use common::{gauge, parse};
+
+/// each gauge entry, and part of the error it's refused with, or `None`
+/// when it's accepted
+const ENTRIES: &[(&str, Option<&str>)] = &[
+    (r#"a = "/a""#, None),
+    (r#"a = 3"#, Some("expected a path, or a table with a path or command")),
+    (r#"a = { path = "/a", refersh_secs = 1 }"#, Some("unknown field `refersh_secs`")),
+    (r#"a = { path = "/a", refresh_secs = 0.5, jitter_secs = 0, max_backoff_secs = 0 }"#, None),
+    (r#"a = { path = "/a", refresh_secs = 0 }"#, Some("interval must be longer than zero")),
+    (r#"a = { path = "/a", refresh_secs = -1 }"#, Some("interval must be longer than zero")),
+    (r#"a = { path = "/a", jitter_secs = -1 }"#, Some("duration must be positive")),
+    // source:
+    (r#"a = { command = ["true"], env = { A = "1" }, timeout_secs = 1 }"#, None),
+    (r#"a = { command = [] }"#, Some("command must not be empty")),
+    (r#"a = { path = "/a", command = ["true"] }"#, Some("exactly one of path and command")),
+    (r#"a = { regex = "x" }"#, Some("exactly one of path and command")),
+    (r#"a = { path = "/a", timeout_secs = 1 }"#, Some("env and timeout_secs only apply to commands")),
+    (r#"a = { command = ["true"], mode = "inotify" }"#, Some("commands can't be watched with inotify")),
+    (r#"a = "/sys/class/net/*/statistics/rx_bytes""#, None),
+    (r#"a = "/sys/**/temp""#, Some("recursive wildcards are not supported")),
+    (r#"a = "/sys/[a/temp""#, Some("invalid glob")),
+    // kind:
+    (r#"a = { path = "/a", type = "histogram" }"#, Some("unknown variant `histogram`")),
+    (r#"a = { path = "/a", wrap_bits = 32 }"#, Some("wrap_bits only applies to counters")),
+    (r#"a = { path = "/a", type = "counter", wrap_bits = 0 }"#, Some("wrap_bits must be between 1 and 63")),
+    (r#"a = { path = "/a", type = "counter", wrap_bits = 64 }"#, Some("wrap_bits must be between 1 and 63")),
+    // extract:
+    (r#"a = { path = "/a", key = "x", column = 1 }"#, None),
+    (r#"a = { path = "/a", key = "x", regex = "y" }"#, Some("regex, key and json_pointer are exclusive")),
+    (r#"a = { path = "/a", column = 1, json_pointer = "/y" }"#, Some("regex, key and json_pointer are exclusive")),
+    (r#"a = { path = "/a", regex = "(" }"#, Some("invalid regex")),
+];
+
+#[test]
+fn entries() {
+    for (entry, error) in ENTRIES {
+        match (parse(entry), error) {
+            (Ok(_), None) => {}
+            (Err(e), Some(error)) => assert!(e.to_string().contains(error), "{}: {}", entry, e),
+            (Ok(_), Some(error)) => panic!("{}: accepted, expected {}", entry, error),
+            (Err(e), None) => panic!("{}: {}", entry, e),
+        }
+    }
+}
+
+#[test]
+fn defaults() {
+    let kind = |entry: &str| gauge(&parse(entry).unwrap(), "a").kind;
+
+    assert!(kind(r#"a = "/a""#) == Kind::Gauge);
+    assert!(kind(r#"a = { path = "/a", type = "gauge" }"#) == Kind::Gauge);
+    assert!(kind(r#"a = { path = "/a", type = "counter" }"#) == Kind::Counter { wrap_bits: None });
+    assert!(kind(r#"a = { command = ["true"], type = "counter", wrap_bits = 32 }"#) == Kind::Counter { wrap_bits: Some(32) });
+
+    let config = parse(r#"a = { command = ["true"] }"#).unwrap();
+    assert!(matches!(gauge(&config, "a").source, Source::Command(command) if command.timeout == Duration::from_secs(10)));
+}
+
+#[test]
+fn watch() {
+    let watch = |watch: &str| toml::from_str::<Config>(&format!("[watch]\n{}\n[gauges]\n", watch));
+
+    assert!(watch("refresh_secs = 0.5\njitter_secs = 0").is_ok());
+    assert!(watch("refresh_secs = 0").is_err());
+    assert!(watch("rescan_secs = 0").is_err());
+}
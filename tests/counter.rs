@@ -0,0 +1,59 @@
+use dprom::file_gauge::counter::{Change, Counter};
+use dprom::file_gauge::transform::{Named, Transform};
+
+#[test]
+fn increases() {
+    let mut counter = Counter::default();
+
+    assert_eq!(counter.observe(100, None), (100, Change::Increase));
+    assert_eq!(counter.observe(100, None), (100, Change::Increase));
+    assert_eq!(counter.observe(250, Some(32)), (250, Change::Increase));
+}
+
+#[test]
+fn wraps() {
+    let mut counter = Counter::default();
+
+    assert_eq!(counter.observe(250, Some(8)), (250, Change::Increase));
+    assert_eq!(counter.observe(10, Some(8)), (266, Change::Wrap));
+    assert_eq!(counter.observe(240, Some(8)), (496, Change::Increase));
+    assert_eq!(counter.observe(5, Some(8)), (517, Change::Wrap));
+}
+
+#[test]
+fn resets() {
+    let mut counter = Counter::default();
+
+    // without wrap_bits, every decrease is a reset:
+    assert_eq!(counter.observe(250, None), (250, Change::Increase));
+    assert_eq!(counter.observe(10, None), (10, Change::Reset));
+
+    // too much would have been counted across a wrap:
+    let mut counter = Counter::default();
+    assert_eq!(counter.observe(100, Some(8)), (100, Change::Increase));
+    assert_eq!(counter.observe(50, Some(8)), (50, Change::Reset));
+
+    // a reset drops the range added for earlier wraps:
+    let mut counter = Counter::default();
+    assert_eq!(counter.observe(250, Some(8)), (250, Change::Increase));
+    assert_eq!(counter.observe(10, Some(8)), (266, Change::Wrap));
+    assert_eq!(counter.observe(3, Some(8)), (3, Change::Reset));
+
+    // values outside the range can't have wrapped:
+    let mut counter = Counter::default();
+    assert_eq!(counter.observe(300, Some(8)), (300, Change::Increase));
+    assert_eq!(counter.observe(10, Some(8)), (10, Change::Reset));
+}
+
+#[test]
+fn counts() {
+    assert_eq!(Transform::default().apply_count("18446744073709551615\n").unwrap(), u64::MAX);
+    assert!(Transform::default().apply_count("-1").is_err());
+    assert!(Transform::default().apply_count("1.5").is_err());
+
+    let kilo = Transform { named: Some(Named::Kilo), ..Default::default() };
+    assert_eq!(kilo.apply_count("1.5").unwrap(), 1500);
+
+    let negative = Transform { offset: -10.0, ..Default::default() };
+    assert!(negative.apply_count("5").is_err());
+}
@@ -0,0 +1,134 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use dprom::file_gauge::config::Source;
+use dprom::file_gauge::expand::expand;
+
+mod common;
+use common::config;
+
+/// a directory under the system temp dir, removed along with its contents
+/// when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("dprom-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// creates a file at `path` in this directory, along with its parents
+    fn file(&self, path: impl AsRef<Path>) {
+        let path = self.path(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "1\n").unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// each gauge `gauges` expands to with the path it reads, `{dir}` in
+/// `gauges` standing for `dir`
+async fn expanded(dir: &TempDir, gauges: &str) -> Vec<(String, PathBuf)> {
+    let config = config(&gauges.replace("{dir}", &dir.0.to_string_lossy()));
+    let log = slog::Logger::root(slog::Discard, slog::o!());
+
+    expand(&log, &config.gauges).await.unwrap()
+        .into_iter()
+        .map(|(name, gauge)| match gauge.source {
+            Source::File(path) => (name.as_str().to_owned(), path),
+            Source::Command(_) => panic!("{} expanded to a command", name.as_str()),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn wildcards() {
+    let dir = TempDir::new("wildcards");
+    dir.file("net/eth0/statistics/rx_bytes");
+    dir.file("net/eth0/statistics/tx_bytes");
+    dir.file("net/eth0/statistics/rx_packets");
+    dir.file("net/wlan0/statistics/rx_bytes");
+    dir.file("hwmon0/temp1_input");
+    dir.file("hwmon0/temp2_input");
+    dir.file("hwmon0/temp3_input");
+    dir.file("hwmon10/temp1_input");
+
+    let gauges = expanded(&dir, r#"
+        bytes = '{dir}/net/*/statistics/*_bytes'
+        temp = '{dir}/hwmon?/temp[12]_input'
+        missing = '{dir}/missing'
+        none = '{dir}/missing/*'
+    "#).await;
+
+    assert_eq!(gauges, [
+        ("bytes_eth0_rx_bytes".to_owned(), dir.path("net/eth0/statistics/rx_bytes")),
+        ("bytes_eth0_tx_bytes".to_owned(), dir.path("net/eth0/statistics/tx_bytes")),
+        ("bytes_wlan0_rx_bytes".to_owned(), dir.path("net/wlan0/statistics/rx_bytes")),
+        ("missing".to_owned(), dir.path("missing")),
+        ("temp_hwmon0_temp1_input".to_owned(), dir.path("hwmon0/temp1_input")),
+        ("temp_hwmon0_temp2_input".to_owned(), dir.path("hwmon0/temp2_input")),
+    ]);
+}
+
+#[tokio::test]
+async fn names() {
+    let dir = TempDir::new("names");
+    dir.file("net/eth-0/rx_bytes");
+    dir.file("net/br:lan.1/rx_bytes");
+    dir.file("net/wlan\u{e9}/rx_bytes");
+
+    // not matched by glob at all:
+    dir.file(Path::new("net").join(OsStr::from_bytes(b"eth\xff")).join("rx_bytes"));
+
+    let gauges = expanded(&dir, r#"rx = '{dir}/net/*/rx_bytes'"#).await;
+
+    assert_eq!(gauges, [
+        ("rx_br_lan_1".to_owned(), dir.path("net/br:lan.1/rx_bytes")),
+        ("rx_eth_0".to_owned(), dir.path("net/eth-0/rx_bytes")),
+        ("rx_wlan_".to_owned(), dir.path("net/wlan\u{e9}/rx_bytes")),
+    ]);
+}
+
+#[tokio::test]
+async fn collisions() {
+    let dir = TempDir::new("collisions");
+    dir.file("net/eth-0/rx_bytes");
+    dir.file("net/eth.0/rx_bytes");
+    dir.file("net/eth1/rx_bytes");
+
+    // a plain gauge keeps its name, and the first path matched keeps a name
+    // shared by several:
+    let gauges = expanded(&dir, r#"
+        rx = '{dir}/net/*/rx_bytes'
+        rx_eth1 = '{dir}/eth1_rx_bytes'
+    "#).await;
+
+    assert_eq!(gauges, [
+        ("rx_eth1".to_owned(), dir.path("eth1_rx_bytes")),
+        ("rx_eth_0".to_owned(), dir.path("net/eth-0/rx_bytes")),
+    ]);
+}
+
+#[test]
+fn with_suffixes() {
+    let config = config(r#"battery = "/a""#);
+    let name = config.gauges.0.keys().next().unwrap();
+
+    assert_eq!(name.with_suffixes([]).as_str(), "battery");
+    assert_eq!(name.with_suffixes(["BAT0"]).as_str(), "battery_BAT0");
+    assert_eq!(name.with_suffixes(["BAT0", "capacity_level"]).as_str(), "battery_BAT0_capacity_level");
+    assert_eq!(name.with_suffixes(["a-b.c", "d/e", "\u{e9}"]).as_str(), "battery_a_b_c_d_e__");
+}
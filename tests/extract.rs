@@ -0,0 +1,108 @@
+use dprom::file_gauge::extract::Extract;
+use regex::Regex;
+
+mod common;
+use common::{config, fixture, gauge};
+
+fn key(key: &str, column: usize) -> Extract {
+    Extract::Key { key: key.to_owned(), column }
+}
+
+fn regex(regex: &str) -> Extract {
+    Extract::Regex(Regex::new(regex).unwrap())
+}
+
+fn pointer(pointer: &str) -> Extract {
+    Extract::JsonPointer(pointer.to_owned())
+}
+
+#[test]
+fn whole_file() {
+    assert_eq!(Extract::Whole.value(&fixture("thermal_zone_temp")).unwrap(), "48500");
+    assert_eq!(Extract::Whole.value(&fixture("proc_loadavg")).unwrap(), "0.68 0.66 0.59 4/874 42198");
+}
+
+#[test]
+fn key_colon() {
+    let meminfo = fixture("proc_meminfo");
+
+    assert_eq!(key("MemAvailable", 0).value(&meminfo).unwrap(), "9738996");
+    assert_eq!(key("HugePages_Total", 0).value(&meminfo).unwrap(), "0");
+    // the unit is a column of its own:
+    assert_eq!(key("MemTotal", 1).value(&meminfo).unwrap(), "kB");
+    assert!(key("MemTotal", 2).value(&meminfo).is_err());
+    // keys must match whole:
+    assert!(key("Mem", 0).value(&meminfo).is_err());
+}
+
+#[test]
+fn key_equals() {
+    let uevent = fixture("power_supply_uevent");
+
+    assert_eq!(key("POWER_SUPPLY_CAPACITY", 0).value(&uevent).unwrap(), "71");
+    assert_eq!(key("POWER_SUPPLY_CHARGE_NOW", 0).value(&uevent).unwrap(), "2958000");
+    assert_eq!(key("POWER_SUPPLY_STATUS", 0).value(&uevent).unwrap(), "Discharging");
+    assert!(key("POWER_SUPPLY_ENERGY_NOW", 0).value(&uevent).is_err());
+}
+
+#[test]
+fn key_quoted() {
+    assert_eq!(key("BUILD_ID", 0).value(&fixture("os_release")).unwrap(), "20261001");
+}
+
+#[test]
+fn column() {
+    let loadavg = fixture("proc_loadavg");
+
+    assert_eq!(Extract::Column(0).value(&loadavg).unwrap(), "0.68");
+    assert_eq!(Extract::Column(2).value(&loadavg).unwrap(), "0.59");
+    assert_eq!(Extract::Column(3).value(&loadavg).unwrap(), "4/874");
+    assert!(Extract::Column(5).value(&loadavg).is_err());
+}
+
+#[test]
+fn regex_capture() {
+    let loadavg = fixture("proc_loadavg");
+    let pressure = fixture("proc_pressure_cpu");
+
+    assert_eq!(regex(r"\d+/(\d+)").value(&loadavg).unwrap(), "874");
+    assert_eq!(regex(r"(?m)^full avg10=(\S+)").value(&pressure).unwrap(), "0.00");
+    assert_eq!(regex(r"avg60=(\S+)").value(&pressure).unwrap(), "0.87");
+    // without a group, the whole match is the value:
+    assert_eq!(regex(r"^\d+").value(&fixture("thermal_zone_temp")).unwrap(), "48500");
+    assert!(regex(r"avg1000=(\S+)").value(&pressure).is_err());
+}
+
+#[test]
+fn json_pointer() {
+    let status = fixture("status.json");
+
+    assert_eq!(pointer("/uptime_seconds").value(&status).unwrap(), "86412.5");
+    assert_eq!(pointer("/healthy").value(&status).unwrap(), "1");
+    assert_eq!(pointer("/queues/0/length").value(&status).unwrap(), "17");
+    assert_eq!(pointer("/queues/1/length").value(&status).unwrap(), "3");
+    assert_eq!(pointer("/version").value(&status).unwrap(), "2.4.1");
+    assert!(pointer("/last_error").value(&status).is_err());
+    assert!(pointer("/queues").value(&status).is_err());
+    assert!(pointer("/queues/2/length").value(&status).is_err());
+    assert!(pointer("/uptime_seconds").value(&fixture("proc_loadavg")).is_err());
+}
+
+#[test]
+fn from_config() {
+    let config = config(r#"
+        temp = "/sys/class/thermal/thermal_zone0/temp"
+        mem_available = { path = "/proc/meminfo", key = "MemAvailable" }
+        load1 = { path = "/proc/loadavg", column = 0 }
+        procs = { path = "/proc/loadavg", regex = '\d+/(\d+)' }
+        queue = { path = "/run/status.json", json_pointer = "/queues/0/length" }
+    "#);
+
+    let extract = |name: &str| gauge(&config, name).extract;
+
+    assert!(matches!(extract("temp"), Extract::Whole));
+    assert!(matches!(extract("mem_available"), Extract::Key { key, column: 0 } if key == "MemAvailable"));
+    assert!(matches!(extract("load1"), Extract::Column(0)));
+    assert!(matches!(extract("procs"), Extract::Regex(regex) if regex.as_str() == r"\d+/(\d+)"));
+    assert!(matches!(extract("queue"), Extract::JsonPointer(pointer) if pointer == "/queues/0/length"));
+}
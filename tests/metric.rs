@@ -0,0 +1,65 @@
+use std::time::{Duration, SystemTime};
+
+use dprom::export::metric::{Export, MetricValue, Source};
+use futures::StreamExt;
+
+fn source() -> Source {
+    Source {
+        connection: "session",
+        bus: ":1.1".to_owned(),
+        path: "/".to_owned(),
+    }
+}
+
+/// the creation time recorded with each of `values` measured in turn
+async fn created(values: Vec<MetricValue>) -> Vec<SystemTime> {
+    let (export, records) = Export::new(slog::Logger::root(slog::Discard, slog::o!()));
+    futures::pin_mut!(records);
+
+    let metric = export.metric("a", source());
+    let mut created = Vec::new();
+
+    for value in values {
+        metric.measure(value, None).await;
+
+        let (_, sample) = records.next().await.unwrap();
+        created.push(sample.unwrap().created);
+
+        // so that a reset is seen at a later time than the samples before:
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    created
+}
+
+fn counter(value: u64) -> MetricValue {
+    MetricValue::Counter { value, exemplar: None }
+}
+
+#[tokio::test]
+async fn counter_reset() {
+    let created = created(vec![counter(5), counter(7), counter(2), counter(3)]).await;
+
+    assert_eq!(created[0], created[1]);
+    assert!(created[2] > created[1]);
+    assert_eq!(created[2], created[3]);
+}
+
+#[tokio::test]
+async fn histogram_reset() {
+    let histogram = |count: u64| MetricValue::Histogram {
+        buckets: vec![(1.0, count)],
+        sum: count as f64,
+        count,
+        exemplars: Vec::new(),
+    };
+
+    let created = created(vec![histogram(5), histogram(2)]).await;
+    assert!(created[1] > created[0]);
+}
+
+#[tokio::test]
+async fn gauge_decrease() {
+    let created = created(vec![MetricValue::Gauge(5.0), MetricValue::Gauge(2.0)]).await;
+    assert_eq!(created[0], created[1]);
+}
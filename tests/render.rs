@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use dprom::export::format::{Format, Selection};
+use dprom::export::live::{Entry, MetricMap};
+use dprom::export::metric::{Exemplar, MetricName, MetricValue, NativeHistogram, Source};
+use dprom::export::proto::{self, MetricFamily, MetricType};
+use dprom::export::selector::SeriesFilter;
+use prost::Message;
+
+/// renders `metrics` as scraped without any filter. every series was
+/// created at 1.5s past the epoch
+fn render(metrics: Vec<(&str, MetricValue)>, format: Format) -> String {
+    String::from_utf8(render_bytes(metrics, format)).unwrap()
+}
+
+fn render_bytes(metrics: Vec<(&str, MetricValue)>, format: Format) -> Vec<u8> {
+    let map = metrics.into_iter()
+        .map(|(name, value)| (MetricName::from(name), entry(value)))
+        .collect::<MetricMap>();
+
+    let filter = SeriesFilter::from_query("").unwrap();
+    Selection::new(&map, &filter, false, None).render(format)
+}
+
+fn entry(value: MetricValue) -> Entry {
+    Entry {
+        value,
+        timestamp: None,
+        created: UNIX_EPOCH + Duration::from_millis(1500),
+        source: Arc::new(Source {
+            connection: "session",
+            bus: ":1.1".to_owned(),
+            path: "/".to_owned(),
+        }),
+        updated: SystemTime::now(),
+    }
+}
+
+fn labels(labels: &[(&str, &str)]) -> BTreeMap<String, String> {
+    labels.iter().map(|(label, value)| (label.to_string(), value.to_string())).collect()
+}
+
+fn exemplar(trace_id: &str, value: f64, timestamp: Option<i64>) -> Exemplar {
+    Exemplar { labels: labels(&[("trace_id", trace_id)]), value, timestamp }
+}
+
+#[test]
+fn accept() {
+    let format = |accept| Format::from_accept(Some(accept));
+
+    assert!(Format::from_accept(None) == Format::Prometheus);
+    assert!(format("application/openmetrics-text; version=1.0.0; charset=utf-8,text/plain;version=0.0.4;q=0.5") == Format::OpenMetrics);
+    assert!(format("application/openmetrics-text;q=0.5,text/plain") == Format::Prometheus);
+    assert!(format("application/openmetrics-text;q=0,text/plain;q=0.1") == Format::Prometheus);
+    assert!(format("application/openmetrics-text;q=junk") == Format::Prometheus);
+
+    // ties go to the first listed:
+    assert!(format("text/plain,application/openmetrics-text") == Format::Prometheus);
+    assert!(format("application/openmetrics-text,text/plain") == Format::OpenMetrics);
+
+    // as sent by Prometheus with native histograms enabled:
+    assert!(format("application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited;q=0.7,\
+        text/plain;version=0.0.4;q=0.3,*/*;q=0.2") == Format::Protobuf);
+
+    // protobuf is only served length delimited:
+    assert!(format("application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily") == Format::Prometheus);
+    assert!(format("application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=text") == Format::Prometheus);
+    assert!(format("application/vnd.google.protobuf;encoding=delimited") == Format::Prometheus);
+
+    // anything unsupported falls back to text:
+    assert!(format("application/json") == Format::Prometheus);
+    assert!(format("") == Format::Prometheus);
+}
+
+#[test]
+fn protobuf() {
+    let metrics = vec![
+        ("empty", MetricValue::NativeHistogram(NativeHistogram {
+            schema: 0,
+            zero_threshold: 0.0,
+            zero_count: 0,
+            count: 0,
+            sum: 0.0,
+            positive_spans: Vec::new(),
+            positive_deltas: Vec::new(),
+            negative_spans: Vec::new(),
+            negative_deltas: Vec::new(),
+        })),
+        ("latency", MetricValue::NativeHistogram(NativeHistogram {
+            schema: 3,
+            zero_threshold: 0.001,
+            zero_count: 1,
+            count: 7,
+            sum: 4.5,
+            positive_spans: vec![(0, 2), (3, 1)],
+            positive_deltas: vec![2, -1, 1],
+            negative_spans: vec![(-2, 1)],
+            negative_deltas: vec![1],
+        })),
+        ("requests_total", MetricValue::Counter { value: 7, exemplar: None }),
+    ];
+
+    let output = render_bytes(metrics, Format::Protobuf);
+    let mut buf = output.as_slice();
+    let mut families = Vec::new();
+
+    while !buf.is_empty() {
+        families.push(MetricFamily::decode_length_delimited(&mut buf).unwrap());
+    }
+
+    let [empty, latency, requests] = families.as_slice() else { panic!("{} families", families.len()) };
+
+    // with neither spans nor a zero bucket, an empty span marks it native:
+    let histogram = empty.metric[0].histogram.as_ref().unwrap();
+    assert_eq!(empty.r#type, Some(MetricType::Histogram as i32));
+    assert_eq!(histogram.positive_span, [span(0, 0)]);
+    assert!(histogram.negative_span.is_empty());
+
+    let histogram = latency.metric[0].histogram.as_ref().unwrap();
+    assert_eq!(latency.name.as_deref(), Some("latency"));
+    assert_eq!(latency.r#type, Some(MetricType::Histogram as i32));
+    assert_eq!(histogram.schema, Some(3));
+    assert_eq!(histogram.zero_threshold, Some(0.001));
+    assert_eq!(histogram.zero_count, Some(1));
+    assert_eq!(histogram.sample_count, Some(7));
+    assert_eq!(histogram.sample_sum, Some(4.5));
+    assert_eq!(histogram.positive_span, [span(0, 2), span(3, 1)]);
+    assert_eq!(histogram.positive_delta, [2, -1, 1]);
+    assert_eq!(histogram.negative_span, [span(-2, 1)]);
+    assert_eq!(histogram.negative_delta, [1]);
+    assert!(histogram.bucket.is_empty());
+    assert_eq!(histogram.created_timestamp, Some(proto::Timestamp { seconds: 1, nanos: 500_000_000 }));
+
+    let counter = requests.metric[0].counter.as_ref().unwrap();
+    assert_eq!(requests.r#type, Some(MetricType::Counter as i32));
+    assert_eq!(counter.value, Some(7.0));
+}
+
+fn span(offset: i32, length: u32) -> proto::BucketSpan {
+    proto::BucketSpan { offset: Some(offset), length: Some(length) }
+}
+
+#[test]
+fn counter() {
+    let metrics = || vec![
+        ("jobs", MetricValue::Counter { value: 3, exemplar: None }),
+        ("requests_total", MetricValue::Counter { value: 7, exemplar: Some(exemplar("abc", 1.0, Some(2500))) }),
+    ];
+
+    // the text family keeps the name as published, OpenMetrics names it
+    // without _total:
+    assert_eq!(render(metrics(), Format::Prometheus), concat!(
+        "# TYPE jobs counter\n",
+        "jobs 3\n",
+        "# TYPE requests_total counter\n",
+        "requests_total 7\n",
+    ));
+
+    assert_eq!(render(metrics(), Format::OpenMetrics), concat!(
+        "# TYPE jobs counter\n",
+        "jobs_total 3\n",
+        "jobs_created 1.500\n",
+        "# TYPE requests counter\n",
+        "requests_total 7 # {trace_id=\"abc\"} 1 2.500\n",
+        "requests_created 1.500\n",
+        "# EOF\n",
+    ));
+}
+
+#[test]
+fn histogram() {
+    let metrics = || vec![
+        ("latency", MetricValue::Histogram {
+            buckets: vec![(0.1, 2), (1.0, 5)],
+            sum: 3.5,
+            count: 6,
+            exemplars: vec![exemplar("abc", 0.5, None), exemplar("def", 0.7, Some(2500))],
+        }),
+    ];
+
+    assert_eq!(render(metrics(), Format::Prometheus), concat!(
+        "# TYPE latency histogram\n",
+        "latency_bucket{le=\"0.1\"} 2\n",
+        "latency_bucket{le=\"1\"} 5\n",
+        "latency_bucket{le=\"+Inf\"} 6\n",
+        "latency_sum 3.5\n",
+        "latency_count 6\n",
+    ));
+
+    // each bucket carries the latest exemplar falling in it, if any:
+    assert_eq!(render(metrics(), Format::OpenMetrics), concat!(
+        "# TYPE latency histogram\n",
+        "latency_bucket{le=\"0.1\"} 2\n",
+        "latency_bucket{le=\"1\"} 5 # {trace_id=\"def\"} 0.7 2.500\n",
+        "latency_bucket{le=\"+Inf\"} 6\n",
+        "latency_sum 3.5\n",
+        "latency_count 6\n",
+        "latency_created 1.500\n",
+        "# EOF\n",
+    ));
+}
+
+#[test]
+fn created() {
+    let metrics = || vec![
+        ("a", MetricValue::Gauge(1.0)),
+        ("b", MetricValue::Counter { value: 1, exemplar: None }),
+        ("c", MetricValue::Histogram { buckets: Vec::new(), sum: 1.0, count: 1, exemplars: Vec::new() }),
+    ];
+
+    let created = |format| render(metrics(), format).lines()
+        .filter(|line| line.contains("_created"))
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+
+    assert_eq!(created(Format::Prometheus), Vec::<String>::new());
+    assert_eq!(created(Format::OpenMetrics), ["b_created 1.500", "c_created 1.500"]);
+}
+
+#[test]
+fn info() {
+    let metrics = || vec![
+        ("build", MetricValue::Info(labels(&[("version", "1.0"), ("git-ref", "a\"b\\c\nd")]))),
+        ("kernel_info", MetricValue::Info(labels(&[("release", "6.1")]))),
+    ];
+
+    assert_eq!(render(metrics(), Format::Prometheus), concat!(
+        "# TYPE build_info gauge\n",
+        "build_info{git_ref=\"a\\\"b\\\\c\\nd\",version=\"1.0\"} 1\n",
+        "# TYPE kernel_info gauge\n",
+        "kernel_info{release=\"6.1\"} 1\n",
+    ));
+
+    assert_eq!(render(metrics(), Format::OpenMetrics), concat!(
+        "# TYPE build info\n",
+        "build_info{git_ref=\"a\\\"b\\\\c\\nd\",version=\"1.0\"} 1\n",
+        "# TYPE kernel info\n",
+        "kernel_info{release=\"6.1\"} 1\n",
+        "# EOF\n",
+    ));
+}
+
+#[test]
+fn stateset() {
+    let states = |state: &str| MetricValue::StateSet {
+        states: vec!["on".to_owned(), "off".to_owned()],
+        state: state.to_owned(),
+    };
+
+    let metrics = || vec![
+        ("node:power", states("un\"known")),
+        ("power", states("off")),
+    ];
+
+    assert_eq!(render(metrics(), Format::Prometheus), concat!(
+        "# TYPE node:power gauge\n",
+        "node:power{node_power=\"on\"} 0\n",
+        "node:power{node_power=\"off\"} 0\n",
+        "node:power{node_power=\"un\\\"known\"} 1\n",
+        "# TYPE power gauge\n",
+        "power{power=\"on\"} 0\n",
+        "power{power=\"off\"} 1\n",
+    ));
+
+    assert_eq!(render(metrics(), Format::OpenMetrics), concat!(
+        "# TYPE node:power stateset\n",
+        "node:power{node_power=\"on\"} 0\n",
+        "node:power{node_power=\"off\"} 0\n",
+        "node:power{node_power=\"un\\\"known\"} 1\n",
+        "# TYPE power stateset\n",
+        "power{power=\"on\"} 0\n",
+        "power{power=\"off\"} 1\n",
+        "# EOF\n",
+    ));
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use dprom::export::selector::{Selector, SeriesFilter};
+
+fn selector(s: &str) -> Selector {
+    s.parse().unwrap()
+}
+
+fn matches(selector: &Selector, name: &str, labels: &[(&str, &str)]) -> bool {
+    let labels = labels.iter().copied().collect::<HashMap<_, _>>();
+    selector.matches(name, |label| labels.get(label).copied())
+}
+
+#[test]
+fn names() {
+    let up = selector("up");
+    assert!(matches(&up, "up", &[]));
+    assert!(!matches(&up, "up_total", &[]));
+
+    let name_label = selector(r#"{__name__="up"}"#);
+    assert!(matches(&name_label, "up", &[]));
+    assert!(!matches(&name_label, "down", &[]));
+
+    assert!(matches(&selector("  node:load1 { } "), "node:load1", &[]));
+}
+
+#[test]
+fn quoting() {
+    let double = selector(r#"up{job="a \"b\" \\ c\n"}"#);
+    assert!(matches(&double, "up", &[("job", "a \"b\" \\ c\n")]));
+
+    let single = selector(r#"up{job='it\'s "quoted"'}"#);
+    assert!(matches(&single, "up", &[("job", "it's \"quoted\"")]));
+
+    let unicode = selector(r#"up{job="ünïcödé",other='x'}"#);
+    assert!(matches(&unicode, "up", &[("job", "ünïcödé"), ("other", "x")]));
+    assert!(!matches(&unicode, "up", &[("job", "ünïcödé")]));
+}
+
+#[test]
+fn operators() {
+    let ne = selector(r#"up{job!="a"}"#);
+    assert!(matches(&ne, "up", &[("job", "b")]));
+    assert!(matches(&ne, "up", &[]));
+    assert!(!matches(&ne, "up", &[("job", "a")]));
+
+    // missing labels compare equal to the empty string:
+    let empty = selector(r#"up{job=""}"#);
+    assert!(matches(&empty, "up", &[]));
+    assert!(!matches(&empty, "up", &[("job", "a")]));
+}
+
+#[test]
+fn regex_anchoring() {
+    let re = selector(r#"{job=~"foo|bar"}"#);
+    assert!(matches(&re, "up", &[("job", "foo")]));
+    assert!(matches(&re, "up", &[("job", "bar")]));
+    assert!(!matches(&re, "up", &[("job", "foobar")]));
+    assert!(!matches(&re, "up", &[("job", "xfoo")]));
+
+    let not_re = selector(r#"{job!~"foo|bar",job!=""}"#);
+    assert!(!matches(&not_re, "up", &[("job", "foo")]));
+    assert!(matches(&not_re, "up", &[("job", "foobar")]));
+
+    let name_re = selector(r#"{__name__=~"node_.+"}"#);
+    assert!(matches(&name_re, "node_load1", &[]));
+    assert!(!matches(&name_re, "go_node_info", &[]));
+}
+
+#[test]
+fn empty_matchers() {
+    assert!("".parse::<Selector>().is_err());
+    assert!("{}".parse::<Selector>().is_err());
+
+    // every series would match these:
+    assert!(r#"{job=""}"#.parse::<Selector>().is_err());
+    assert!(r#"{job=~".*"}"#.parse::<Selector>().is_err());
+    assert!(r#"{job!="a",other=~"x?"}"#.parse::<Selector>().is_err());
+
+    assert!(r#"{job!=""}"#.parse::<Selector>().is_ok());
+    assert!(r#"{job=~".+",other=""}"#.parse::<Selector>().is_ok());
+}
+
+#[test]
+fn errors() {
+    for invalid in [
+        r#"up{job="a""#,
+        r#"up{job="a}"#,
+        r#"up{job=a}"#,
+        r#"up{job}"#,
+        r#"up{job=="a"}"#,
+        r#"up{="a"}"#,
+        r#"up{job="a" other="b"}"#,
+        r#"up{job="a"} x"#,
+        r#"up{job=~"("}"#,
+        r#"up job"#,
+    ] {
+        assert!(invalid.parse::<Selector>().is_err(), "{} should not parse", invalid);
+    }
+}
+
+#[test]
+fn series_filter() {
+    let filter = SeriesFilter::from_query("").unwrap();
+    assert!(filter.matches("anything"));
+
+    let filter = SeriesFilter::from_query("name[]=up&name[]=load1").unwrap();
+    assert!(filter.matches("up"));
+    assert!(!filter.matches("down"));
+
+    // match[] selectors are alternatives, name[] narrows them down:
+    let filter = SeriesFilter::from_query("match[]=up&match[]=%7B__name__%3D~%22node_.%2B%22%7D&name[]=up&name[]=node_load1").unwrap();
+    assert!(filter.matches("up"));
+    assert!(filter.matches("node_load1"));
+    assert!(!filter.matches("node_load5"));
+
+    assert!(SeriesFilter::from_query("match[]=%7B%7D").is_err());
+}
@@ -0,0 +1,85 @@
+use dprom::file_gauge::transform::{Named, Transform};
+
+mod common;
+use common::{config, fixture, gauge};
+
+fn named(named: Named) -> Transform {
+    Transform { named: Some(named), ..Default::default() }
+}
+
+#[test]
+fn identity() {
+    assert_eq!(Transform::default().apply("42").unwrap(), 42.0);
+    assert_eq!(Transform::default().apply(" -1.5\n").unwrap(), -1.5);
+    assert!(Transform::default().apply("Charging").is_err());
+}
+
+#[test]
+fn named_units() {
+    assert_eq!(named(Named::Milli).apply("48500").unwrap(), 48.5);
+    assert_eq!(named(Named::Micro).apply("2958000").unwrap(), 2.958);
+    assert_eq!(named(Named::Nano).apply("1500000000").unwrap(), 1.5);
+    assert_eq!(named(Named::Kilo).apply("9738996").unwrap(), 9738996000.0);
+    assert_eq!(named(Named::Percent).apply("71").unwrap(), 0.71);
+}
+
+#[test]
+fn named_bool() {
+    let bool = named(Named::Bool);
+
+    assert_eq!(bool.apply("true").unwrap(), 1.0);
+    assert_eq!(bool.apply("Enabled").unwrap(), 1.0);
+    assert_eq!(bool.apply("OFF").unwrap(), 0.0);
+    assert_eq!(bool.apply("1").unwrap(), 1.0);
+    assert!(bool.apply("maybe").is_err());
+}
+
+#[test]
+fn scale_offset() {
+    // decikelvin to degrees celsius:
+    let transform = Transform { scale: 0.1, offset: -273.15, ..Default::default() };
+    assert!((transform.apply("3215").unwrap() - 48.35).abs() < 1e-9);
+
+    // applied after the named transform:
+    let transform = Transform { scale: 2.0, offset: 1.0, ..named(Named::Milli) };
+    assert_eq!(transform.apply("500").unwrap(), 2.0);
+}
+
+#[test]
+fn map() {
+    let transform = Transform {
+        map: [("Charging", 1.0), ("Discharging", -1.0), ("Full", 0.0)].into_iter()
+            .map(|(status, value)| (status.to_owned(), value))
+            .collect(),
+        ..Default::default()
+    };
+
+    assert_eq!(transform.apply("Charging").unwrap(), 1.0);
+    assert_eq!(transform.apply("Discharging\n").unwrap(), -1.0);
+    assert_eq!(transform.apply("7").unwrap(), 7.0);
+    assert!(transform.apply("Unknown").is_err());
+}
+
+#[test]
+fn from_config() {
+    let config = config(r#"
+        temp = { path = "/sys/class/thermal/thermal_zone0/temp", transform = "milli" }
+        charge = { path = "/sys/class/power_supply/BAT1/uevent", key = "POWER_SUPPLY_CHARGE_NOW", transform = "micro" }
+        capacity = { path = "/sys/class/power_supply/BAT1/uevent", key = "POWER_SUPPLY_CAPACITY", transform = "percent" }
+        status = { path = "/sys/class/power_supply/BAT1/uevent", key = "POWER_SUPPLY_STATUS", map = { Charging = 1, Discharging = 0 } }
+        mem = { path = "/proc/meminfo", key = "MemAvailable", transform = "kilo", scale = 0.5, offset = 1 }
+    "#);
+
+    let read = |name: &str, fixture_name: &str| {
+        let gauge = gauge(&config, name);
+        let contents = fixture(fixture_name);
+        let value = gauge.extract.value(&contents).unwrap();
+        gauge.transform.apply(&value).unwrap()
+    };
+
+    assert_eq!(read("temp", "thermal_zone_temp"), 48.5);
+    assert_eq!(read("charge", "power_supply_uevent"), 2.958);
+    assert_eq!(read("capacity", "power_supply_uevent"), 0.71);
+    assert_eq!(read("status", "power_supply_uevent"), 0.0);
+    assert_eq!(read("mem", "proc_meminfo"), 4869498001.0);
+}